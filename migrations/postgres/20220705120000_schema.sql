@@ -0,0 +1,173 @@
+-- The schema the SQLite migrations build up to, for PostgreSQL. Discord ids don't fit in INTEGER, so every id is a BIGINT.
+CREATE TABLE servers (
+    id BIGINT PRIMARY KEY,
+    voice_channel BIGINT,
+    text_channel BIGINT
+);
+
+CREATE TABLE users (
+    id BIGINT NOT NULL,
+    server_id BIGINT NOT NULL,
+    voice TEXT,
+
+    PRIMARY KEY(id, server_id),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE dictionary (
+    server_id BIGINT,
+    word TEXT,
+    replacement TEXT,
+
+    PRIMARY KEY(server_id, word),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE filters (
+    server_id BIGINT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    builtin BOOLEAN NOT NULL DEFAULT TRUE,
+    policy TEXT NOT NULL DEFAULT 'bleep',
+    replacement TEXT NOT NULL DEFAULT 'beep',
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE filter_words (
+    server_id BIGINT,
+    word TEXT,
+
+    PRIMARY KEY(server_id, word),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- max_length and collapse moved to the settings table
+CREATE TABLE spam_settings (
+    server_id BIGINT PRIMARY KEY,
+    user_messages BIGINT NOT NULL DEFAULT 5,
+    user_seconds BIGINT NOT NULL DEFAULT 10,
+    server_messages BIGINT NOT NULL DEFAULT 20,
+    server_seconds BIGINT NOT NULL DEFAULT 10,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- user_id 0 is the server-wide mapping
+CREATE TABLE language_voices (
+    server_id BIGINT,
+    user_id BIGINT,
+    language TEXT,
+    voice TEXT,
+
+    PRIMARY KEY(server_id, user_id, language),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE voice_options (
+    id BIGINT,
+    server_id BIGINT,
+    rate REAL NOT NULL DEFAULT 1.0,
+    pitch REAL NOT NULL DEFAULT 0.0,
+    gain REAL NOT NULL DEFAULT 0.0,
+    effect TEXT NOT NULL DEFAULT 'none',
+
+    PRIMARY KEY(id, server_id),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE presets (
+    server_id BIGINT,
+    name TEXT,
+    voice TEXT NOT NULL,
+    rate REAL NOT NULL DEFAULT 1.0,
+    pitch REAL NOT NULL DEFAULT 0.0,
+    gain REAL NOT NULL DEFAULT 0.0,
+    effect TEXT NOT NULL DEFAULT 'none',
+
+    PRIMARY KEY(server_id, name),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE voice_policy (
+    server_id BIGINT PRIMARY KEY,
+    default_voice TEXT NOT NULL DEFAULT 'tiktok-en_us_002',
+    allowlist BOOLEAN NOT NULL DEFAULT FALSE,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE voice_rules (
+    server_id BIGINT,
+    entry TEXT,
+
+    PRIMARY KEY(server_id, entry),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE user_profiles (
+    id BIGINT PRIMARY KEY,
+    voice TEXT,
+    rate REAL,
+    pitch REAL,
+    gain REAL,
+    effect TEXT,
+    alias TEXT
+);
+
+-- server_id 0 is the global opt-out
+CREATE TABLE consent (
+    server_id BIGINT,
+    user_id BIGINT,
+    opted_in BOOLEAN NOT NULL,
+
+    PRIMARY KEY(server_id, user_id)
+);
+
+CREATE TABLE privacy_settings (
+    server_id BIGINT PRIMARY KEY,
+    optin_only BOOLEAN NOT NULL DEFAULT FALSE,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE blocks (
+    server_id BIGINT,
+    kind TEXT,
+    target_id BIGINT,
+
+    PRIMARY KEY(server_id, kind, target_id),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE access_settings (
+    server_id BIGINT PRIMARY KEY,
+    tts_role BIGINT,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE permission_settings (
+    server_id BIGINT PRIMARY KEY,
+    dj_role BIGINT,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE ignored_prefixes (
+    server_id BIGINT,
+    entry TEXT,
+
+    PRIMARY KEY(server_id, entry),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- See migrations/sqlite/20220701120000_settings_registry.sql
+CREATE TABLE settings (
+    level TEXT NOT NULL,
+    server_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+
+    PRIMARY KEY(level, server_id, target_id, key)
+);
@@ -0,0 +1,9 @@
+-- User-level settings are no longer kept per server but under server_id 0, like in DMs.
+-- Users who set one in several servers keep the value from the server with the lowest id, or the one set in DMs.
+UPDATE settings SET server_id = 0
+WHERE level = 'user' AND server_id = (
+    SELECT MIN(other.server_id) FROM settings AS other
+    WHERE other.level = 'user' AND other.target_id = settings.target_id AND other.key = settings.key
+);
+
+DELETE FROM settings WHERE level = 'user' AND server_id <> 0;
@@ -0,0 +1,3 @@
+-- Before server default voices and global profiles, everyone who spoke was stored with the bot's default voice,
+-- which would now hide both. Users who really want it can set it again.
+UPDATE users SET voice = NULL WHERE voice = 'tiktok-en_us_002';
@@ -0,0 +1,5 @@
+-- The server's default voice is the `voice` setting now, so it can also be set for a channel or every server
+INSERT INTO settings (level, server_id, target_id, key, value)
+SELECT 'server', server_id, server_id, 'voice', default_voice FROM voice_policy;
+
+-- voice_policy.default_voice is no longer read
@@ -0,0 +1,179 @@
+-- Settings tables. IF NOT EXISTS because databases created before migrations were used already have them
+
+-- Server-level pronunciation overrides, applied on top of the default normalization rules
+CREATE TABLE IF NOT EXISTS dictionary (
+    server_id INTEGER,
+    word TEXT,
+    replacement TEXT,
+
+    PRIMARY KEY(server_id, word),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Content filter for spoken output
+CREATE TABLE IF NOT EXISTS filters (
+    server_id INTEGER PRIMARY KEY,
+    enabled BOOLEAN NOT NULL DEFAULT 0,
+    builtin BOOLEAN NOT NULL DEFAULT 1,
+    policy TEXT NOT NULL DEFAULT 'bleep',
+    replacement TEXT NOT NULL DEFAULT 'beep',
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+CREATE TABLE IF NOT EXISTS filter_words (
+    server_id INTEGER,
+    word TEXT,
+
+    PRIMARY KEY(server_id, word),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Anti-spam limits, zero disables a limit
+CREATE TABLE IF NOT EXISTS spam_settings (
+    server_id INTEGER PRIMARY KEY,
+    user_messages INTEGER NOT NULL DEFAULT 5,
+    user_seconds INTEGER NOT NULL DEFAULT 10,
+    server_messages INTEGER NOT NULL DEFAULT 20,
+    server_seconds INTEGER NOT NULL DEFAULT 10,
+    max_length INTEGER NOT NULL DEFAULT 300,
+    collapse BOOLEAN NOT NULL DEFAULT 1,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Voices to switch to when a message is detected to be in another language.
+-- A user_id of 0 is a server-wide mapping.
+CREATE TABLE IF NOT EXISTS language_voices (
+    server_id INTEGER,
+    user_id INTEGER,
+    language TEXT,
+    voice TEXT,
+
+    PRIMARY KEY(server_id, user_id, language),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Per-user voice effects, alongside users.voice
+CREATE TABLE IF NOT EXISTS voice_options (
+    id INTEGER,
+    server_id INTEGER,
+    rate REAL NOT NULL DEFAULT 1.0,
+    pitch REAL NOT NULL DEFAULT 0.0,
+    gain REAL NOT NULL DEFAULT 0.0,
+    effect TEXT NOT NULL DEFAULT 'none',
+
+    PRIMARY KEY(id, server_id),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Loudness normalization target in LUFS (NULL to disable) and master volume
+CREATE TABLE IF NOT EXISTS audio_settings (
+    server_id INTEGER PRIMARY KEY,
+    loudness REAL DEFAULT -16.0,
+    volume REAL NOT NULL DEFAULT 1.0,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Named voice presets, e.g. narrator = ttsmp3-Brian at 0.9x speed
+CREATE TABLE IF NOT EXISTS presets (
+    server_id INTEGER,
+    name TEXT,
+    voice TEXT NOT NULL,
+    rate REAL NOT NULL DEFAULT 1.0,
+    pitch REAL NOT NULL DEFAULT 0.0,
+    gain REAL NOT NULL DEFAULT 0.0,
+    effect TEXT NOT NULL DEFAULT 'none',
+
+    PRIMARY KEY(server_id, name),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Voice users get until they pick one, and whether voice_rules is an allowlist or a denylist
+CREATE TABLE IF NOT EXISTS voice_policy (
+    server_id INTEGER PRIMARY KEY,
+    default_voice TEXT NOT NULL DEFAULT 'tiktok-en_us_002',
+    allowlist INTEGER NOT NULL DEFAULT 0,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Providers (e.g. tiktok) or voice ids (e.g. ttsmp3-brian), lowercase
+CREATE TABLE IF NOT EXISTS voice_rules (
+    server_id INTEGER,
+    entry TEXT,
+
+    PRIMARY KEY(server_id, entry),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Settings that follow a user across servers. NULL means not set, the effect columns are set together
+CREATE TABLE IF NOT EXISTS user_profiles (
+    id INTEGER PRIMARY KEY,
+    voice TEXT,
+    rate REAL,
+    pitch REAL,
+    gain REAL,
+    effect TEXT,
+    alias TEXT
+);
+
+-- Users who opted in or out of having their messages read. server_id 0 is a global opt-out
+CREATE TABLE IF NOT EXISTS consent (
+    server_id INTEGER,
+    user_id INTEGER,
+    opted_in INTEGER NOT NULL,
+
+    PRIMARY KEY(server_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS privacy_settings (
+    server_id INTEGER PRIMARY KEY,
+    optin_only INTEGER NOT NULL DEFAULT 0,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Users and roles whose messages are never read. kind is 'user' or 'role'
+CREATE TABLE IF NOT EXISTS blocks (
+    server_id INTEGER,
+    kind TEXT,
+    target_id INTEGER,
+
+    PRIMARY KEY(server_id, kind, target_id),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- If tts_role is set, only members with that role are read
+CREATE TABLE IF NOT EXISTS access_settings (
+    server_id INTEGER PRIMARY KEY,
+    tts_role INTEGER,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Role that can run join, leave and the settings commands without Discord permissions
+CREATE TABLE IF NOT EXISTS permission_settings (
+    server_id INTEGER PRIMARY KEY,
+    dj_role INTEGER,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- The bot's command prefix, if the server changed it from tts!
+CREATE TABLE IF NOT EXISTS prefix_settings (
+    server_id INTEGER PRIMARY KEY,
+    prefix TEXT NOT NULL,
+
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+-- Messages starting with these prefixes, or matching these /regexes/, are other bots' commands and not read
+CREATE TABLE IF NOT EXISTS ignored_prefixes (
+    server_id INTEGER,
+    entry TEXT,
+
+    PRIMARY KEY(server_id, entry),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
@@ -0,0 +1,19 @@
+-- users had no key, so racing inserts could add the same user to a server twice.
+-- Keep one row per user and server and make (id, server_id) the primary key.
+-- Duplicates were always updated together, so any of their voices will do.
+CREATE TABLE users_new (
+    id INTEGER NOT NULL,
+    server_id INTEGER NOT NULL,
+    voice TEXT,
+
+    PRIMARY KEY(id, server_id),
+    FOREIGN KEY(server_id) REFERENCES servers(id)
+);
+
+INSERT INTO users_new (id, server_id, voice)
+SELECT id, server_id, MAX(voice) FROM users
+WHERE id IS NOT NULL AND server_id IS NOT NULL
+GROUP BY id, server_id;
+
+DROP TABLE users;
+ALTER TABLE users_new RENAME TO users;
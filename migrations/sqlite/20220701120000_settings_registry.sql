@@ -0,0 +1,30 @@
+-- Settings from the registry in src/settings.rs, stored as text and set at a level:
+-- 'user' (target_id is the user), 'channel' (the channel), 'server' (the server) or 'global' (server_id and target_id are 0).
+CREATE TABLE settings (
+    level TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    target_id INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+
+    PRIMARY KEY(level, server_id, target_id, key)
+);
+
+INSERT INTO settings (level, server_id, target_id, key, value)
+SELECT 'server', server_id, server_id, 'prefix', prefix FROM prefix_settings;
+
+INSERT INTO settings (level, server_id, target_id, key, value)
+SELECT 'server', server_id, server_id, 'volume', CAST(CAST(ROUND(volume * 100) AS INTEGER) AS TEXT) FROM audio_settings;
+
+INSERT INTO settings (level, server_id, target_id, key, value)
+SELECT 'server', server_id, server_id, 'loudness', COALESCE(CAST(loudness AS TEXT), 'off') FROM audio_settings;
+
+INSERT INTO settings (level, server_id, target_id, key, value)
+SELECT 'server', server_id, server_id, 'max_length', CAST(max_length AS TEXT) FROM spam_settings;
+
+INSERT INTO settings (level, server_id, target_id, key, value)
+SELECT 'server', server_id, server_id, 'collapse', CASE WHEN collapse THEN 'on' ELSE 'off' END FROM spam_settings;
+
+DROP TABLE prefix_settings;
+DROP TABLE audio_settings;
+-- spam_settings.max_length and spam_settings.collapse are no longer read, the rate limits stay in spam_settings
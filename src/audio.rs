@@ -0,0 +1,278 @@
+// Audio post-processing, done with the same ffmpeg binary songbird uses for playback.
+
+use std::fmt;
+use std::str::FromStr;
+use anyhow::{bail, Result};
+use tokio::process::Command;
+
+/// An optional effect applied to a user's voice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    None,
+    Echo,
+    Radio,
+    Robot,
+}
+
+impl FromStr for Effect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Effect::None),
+            "echo" => Ok(Effect::Echo),
+            "radio" => Ok(Effect::Radio),
+            "robot" => Ok(Effect::Robot),
+            _ => Err(format!("Unknown effect `{}`, expected `none`, `echo`, `radio` or `robot`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Effect::None => write!(f, "none"),
+            Effect::Echo => write!(f, "echo"),
+            Effect::Radio => write!(f, "radio"),
+            Effect::Robot => write!(f, "robot"),
+        }
+    }
+}
+
+/// Per-user voice settings, applied on top of whatever the backend returns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Effects {
+    /// Speaking rate multiplier, 0.5 to 2.0
+    pub rate: f32,
+    /// Pitch shift in semitones, -12 to 12
+    pub pitch: f32,
+    /// Volume change in dB, -20 to 10
+    pub gain: f32,
+    pub effect: Effect,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Effects { rate: 1.0, pitch: 0.0, gain: 0.0, effect: Effect::None }
+    }
+}
+
+impl Effects {
+    pub const RATE_RANGE: (f32, f32) = (0.5, 2.0);
+    pub const PITCH_RANGE: (f32, f32) = (-12.0, 12.0);
+    pub const GAIN_RANGE: (f32, f32) = (-20.0, 10.0);
+
+    /// Stack another set of effects on top of these, e.g. a user's own options on top of a preset.
+    /// The other set's effect wins unless it has none.
+    pub fn combine(&self, other: &Effects) -> Effects {
+        Effects {
+            rate: (self.rate * other.rate).clamp(Self::RATE_RANGE.0, Self::RATE_RANGE.1),
+            pitch: (self.pitch + other.pitch).clamp(Self::PITCH_RANGE.0, Self::PITCH_RANGE.1),
+            gain: (self.gain + other.gain).clamp(Self::GAIN_RANGE.0, Self::GAIN_RANGE.1),
+            effect: if other.effect == Effect::None { self.effect } else { other.effect },
+        }
+    }
+
+    /// ffmpeg filters applied to the whole message, if any
+    pub fn post_filter(&self) -> Option<String> {
+        let mut filters = Vec::new();
+        if self.gain != 0.0 {
+            filters.push(format!("volume={}dB", self.gain));
+        }
+        match self.effect {
+            Effect::None => {}
+            Effect::Echo => filters.push("aecho=0.8:0.88:60:0.4".to_string()),
+            Effect::Radio => filters.push("highpass=f=300,lowpass=f=3400,acompressor=threshold=0.1:ratio=4".to_string()),
+            Effect::Robot => filters.push("afftfilt=real='hypot(re,im)*sin(0)':imag='hypot(re,im)*cos(0)':win_size=512:overlap=0.75".to_string()),
+        }
+        if filters.is_empty() { None } else { Some(filters.join(",")) }
+    }
+}
+
+/// Container formats the TTS backends are known to return
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Mp3,
+    Wav,
+    Ogg,
+    Opus,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::Wav => "wav",
+            Format::Ogg => "ogg",
+            Format::Opus => "opus",
+        }
+    }
+}
+
+/// Anything shorter than this can't be a useful clip
+const MIN_AUDIO_BYTES: usize = 128;
+
+/// Work out what kind of audio a backend returned from its first bytes.
+/// Backends sometimes answer with an HTML or JSON error page, which is rejected here
+/// rather than being handed to songbird.
+pub fn sniff(data: &[u8]) -> Result<Format> {
+    if data.starts_with(b"ID3") {
+        return Ok(Format::Mp3);
+    }
+    // MPEG audio frame sync, with a layer that isn't the AAC/reserved one
+    if data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0 && data[1] & 0x06 != 0 {
+        return Ok(Format::Mp3);
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WAVE" {
+        return Ok(Format::Wav);
+    }
+    if data.starts_with(b"OggS") {
+        let head = &data[..data.len().min(64)];
+        if head.windows(8).any(|w| w == b"OpusHead") {
+            return Ok(Format::Opus);
+        }
+        return Ok(Format::Ogg);
+    }
+
+    let text = String::from_utf8_lossy(&data[..data.len().min(200)]);
+    let text = text.trim_start();
+    if text.starts_with('<') || text.starts_with('{') || text.starts_with('[') {
+        bail!("Backend returned text instead of audio: {}", text.lines().next().unwrap_or_default());
+    }
+    if data.len() < MIN_AUDIO_BYTES {
+        bail!("Backend returned {} bytes, which is too short to be audio", data.len());
+    }
+    bail!("Backend returned audio in an unknown format")
+}
+
+impl fmt::Display for Effects {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.rate != 1.0 {
+            parts.push(format!("rate {}x", self.rate));
+        }
+        if self.pitch != 0.0 {
+            parts.push(format!("pitch {:+} semitones", self.pitch));
+        }
+        if self.gain != 0.0 {
+            parts.push(format!("gain {:+} dB", self.gain));
+        }
+        if self.effect != Effect::None {
+            parts.push(format!("{} effect", self.effect));
+        }
+        if parts.is_empty() {
+            write!(f, "no effects")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Range of loudness targets a server can pick, in LUFS
+pub const LOUDNESS_RANGE: (f32, f32) = (-30.0, -5.0);
+
+/// ffmpeg filter normalizing loudness to a target in LUFS (EBU R128).
+/// loudnorm upsamples to 192kHz, so bring it back down afterwards.
+pub fn loudnorm(target: f32) -> String {
+    format!("loudnorm=I={}:TP=-1.5:LRA=11,aresample=48000", target)
+}
+
+/// A piece of audio to be stitched together by `concat`.
+pub enum Part {
+    /// An audio file on disk, played back at a tempo multiplier and shifted by a number of semitones
+    File { path: String, tempo: f32, pitch: f32 },
+    /// A 1kHz tone lasting the given number of milliseconds
+    Tone(u32),
+    /// Silence lasting the given number of milliseconds
+    Silence(u32),
+}
+
+/// ffmpeg's atempo only takes factors between 0.5 and 2, so chain it for anything outside that
+fn atempo(mut tempo: f32) -> String {
+    let mut filters = Vec::new();
+    while tempo > 2.0 {
+        filters.push(",atempo=2".to_string());
+        tempo /= 2.0;
+    }
+    while tempo < 0.5 {
+        filters.push(",atempo=0.5".to_string());
+        tempo /= 0.5;
+    }
+    if tempo != 1.0 {
+        filters.push(format!(",atempo={}", tempo));
+    }
+    filters.concat()
+}
+
+/// Concatenate several pieces of audio into a single file, then apply `post` to the result.
+/// Every part is resampled first, since each TTS backend returns a different sample rate.
+pub async fn concat(parts: &[Part], post: Option<&str>, output: &str) -> Result<()> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-y", "-loglevel", "error"]);
+    for part in parts {
+        match part {
+            Part::File { path, .. } => {
+                command.args(["-i", path]);
+            }
+            Part::Tone(ms) => {
+                let source = format!("sine=frequency=1000:duration={}", *ms as f32 / 1000.0);
+                command.args(["-f", "lavfi", "-i", &source]);
+            }
+            Part::Silence(ms) => {
+                let source = format!("anullsrc=r=48000:cl=mono:d={}", *ms as f32 / 1000.0);
+                command.args(["-f", "lavfi", "-i", &source]);
+            }
+        }
+    }
+
+    let mut filter = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        let adjust = match part {
+            Part::File { tempo, pitch, .. } if *pitch != 0.0 => {
+                // Raise the sample rate to shift the pitch, then stretch it back to the wanted tempo
+                let factor = 2f32.powf(*pitch / 12.0);
+                format!(",asetrate={},aresample=48000{}", (48000.0 * factor).round() as u32, atempo(tempo / factor))
+            }
+            Part::File { tempo, .. } => atempo(*tempo),
+            _ => String::new(),
+        };
+        filter.push_str(&format!("[{i}:a]aresample=48000,aformat=sample_fmts=s16:channel_layouts=mono{adjust}[a{i}];"));
+    }
+    for i in 0..parts.len() {
+        filter.push_str(&format!("[a{i}]"));
+    }
+    filter.push_str(&format!("concat=n={}:v=0:a=1", parts.len()));
+    if let Some(post) = post {
+        filter.push_str(&format!(",{}", post));
+    }
+    filter.push_str("[out]");
+    command.args(["-filter_complex", &filter, "-map", "[out]", output]);
+
+    run(command).await
+}
+
+async fn run(mut command: Command) -> Result<()> {
+    let output = command.output().await?;
+    if !output.status.success() {
+        bail!("ffmpeg exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.resize(256, 0);
+        assert_eq!(sniff(&wav).unwrap(), Format::Wav);
+        assert_eq!(sniff(b"ID3\x04\0\0").unwrap(), Format::Mp3);
+        assert_eq!(sniff(&[0xFF, 0xFB, 0x90, 0x64]).unwrap(), Format::Mp3);
+        assert_eq!(sniff(b"OggS\0\x02\0\0\0\0\0\0\0\0OpusHead").unwrap(), Format::Opus);
+        assert!(sniff(b"<!DOCTYPE html><html>502 Bad Gateway</html>").is_err());
+        assert!(sniff(b"{\"status_code\": 1}").is_err());
+        assert!(sniff(b"").is_err());
+    }
+}
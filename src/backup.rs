@@ -0,0 +1,364 @@
+// The file `tts!export` writes and `tts!import` reads: a server's channels, dictionary, presets, filter, settings and users' voices as JSON.
+// Files are checked field by field before anything is imported, so every problem can be reported at once.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use crate::audio::{Effect, Effects};
+use crate::filter::Policy;
+use crate::settings::{self as registry, Level};
+use crate::voices;
+
+/// Bump when the format changes, and keep reading the older versions
+pub const VERSION: u64 = 2;
+/// Every field, with the version it was added in
+const FIELDS: &[(&str, u64)] = &[
+    ("version", 1), ("voice_channel", 2), ("text_channel", 1), ("dictionary", 1),
+    ("presets", 1), ("filter", 1), ("settings", 2), ("users", 1),
+];
+
+/// Voice options as they are written to the file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupOptions {
+    pub rate: f32,
+    pub pitch: f32,
+    pub gain: f32,
+    pub effect: String,
+}
+
+impl From<Effects> for BackupOptions {
+    fn from(effects: Effects) -> Self {
+        BackupOptions { rate: effects.rate, pitch: effects.pitch, gain: effects.gain, effect: effects.effect.to_string() }
+    }
+}
+
+impl BackupOptions {
+    /// Only valid once the file was checked by `parse`
+    pub fn effects(&self) -> Effects {
+        Effects { rate: self.rate, pitch: self.pitch, gain: self.gain, effect: self.effect.parse().unwrap_or(Effect::None) }
+    }
+
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>) {
+        let ranges = [("rate", self.rate, Effects::RATE_RANGE), ("pitch", self.pitch, Effects::PITCH_RANGE), ("gain", self.gain, Effects::GAIN_RANGE)];
+        for (name, value, (min, max)) in ranges {
+            if !(value >= min && value <= max) {
+                errors.push(FieldError::new(format!("{}.{}", path, name), format!("Expected a number between {} and {}", min, max)));
+            }
+        }
+        if let Err(e) = self.effect.parse::<Effect>() {
+            errors.push(FieldError::new(format!("{}.effect", path), e));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupPreset {
+    pub name: String,
+    pub voice: String,
+    pub options: BackupOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupFilter {
+    pub enabled: bool,
+    pub builtin: bool,
+    pub policy: String,
+    pub replacement: String,
+    pub words: Vec<String>,
+}
+
+/// A user's voice and options in the server. `None` follows their global profile or the server's default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupUser {
+    /// Ids are strings, since JSON numbers can't hold every Discord id
+    pub id: String,
+    pub voice: Option<String>,
+    pub options: Option<BackupOptions>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u64,
+    /// The channels the bot joins and reads, which only mean something in the server they came from
+    pub voice_channel: Option<String>,
+    pub text_channel: Option<String>,
+    /// Lowercase word -> replacement
+    pub dictionary: BTreeMap<String, String>,
+    pub presets: Vec<BackupPreset>,
+    pub filter: BackupFilter,
+    /// Settings from `settings::REGISTRY` set for the whole server, name -> value.
+    /// `None` in files from version 1, which leave the settings as they are.
+    pub settings: Option<BTreeMap<String, String>>,
+    pub users: Vec<BackupUser>,
+}
+
+/// Something wrong with one field of an imported file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    /// Where the field is, e.g. `presets[2].options.rate`
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError { field: field.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}`: {}", self.field, self.message)
+    }
+}
+
+/// A Discord id written as a string
+pub fn parse_id(id: &str) -> Option<i64> {
+    id.parse::<u64>().ok().filter(|id| *id > 0).map(|id| id as i64)
+}
+
+/// A single word, as the dictionary and filter commands take them
+fn check_word(word: &str, path: String, errors: &mut Vec<FieldError>) {
+    if word.is_empty() || word.chars().any(char::is_whitespace) {
+        errors.push(FieldError::new(path, "Expected a single word"));
+    }
+}
+
+/// Deserialize one field, recording an error for it if it's missing or has the wrong shape
+fn field<T: DeserializeOwned>(object: &Map<String, Value>, name: &str, errors: &mut Vec<FieldError>) -> Option<T> {
+    match object.get(name) {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| errors.push(FieldError::new(name, e.to_string())))
+            .ok(),
+        None => {
+            errors.push(FieldError::new(name, "Missing"));
+            None
+        }
+    }
+}
+
+/// Deserialize a list item by item, so each broken item gets its own error
+fn list<T: DeserializeOwned>(object: &Map<String, Value>, name: &str, errors: &mut Vec<FieldError>) -> Option<Vec<T>> {
+    let items = field::<Vec<Value>>(object, name, errors)?;
+    let before = errors.len();
+    let items = items.into_iter()
+        .enumerate()
+        .filter_map(|(i, item)| serde_json::from_value(item)
+            .map_err(|e| errors.push(FieldError::new(format!("{}[{}]", name, i), e.to_string())))
+            .ok())
+        .collect();
+    if errors.len() == before { Some(items) } else { None }
+}
+
+/// Read a file written by `tts!export`, checking every field
+pub fn parse(data: &[u8]) -> Result<Backup, Vec<FieldError>> {
+    let value: Value = serde_json::from_slice(data)
+        .map_err(|e| vec![FieldError::new("file", format!("Not valid JSON: {}", e))])?;
+    let object = value.as_object()
+        .ok_or_else(|| vec![FieldError::new("file", "Expected a JSON object")])?;
+    // Nothing else can be read without knowing the version
+    let version = match object.get("version") {
+        Some(version) => match version.as_u64() {
+            Some(version) if version > VERSION => {
+                return Err(vec![FieldError::new("version", format!("The file is from a newer version of the bot, which writes version {}. This one reads up to version {}", version, VERSION))]);
+            }
+            Some(version) if version >= 1 => version,
+            _ => return Err(vec![FieldError::new("version", format!("Unsupported version {}, expected 1 to {}", version, VERSION))]),
+        },
+        None => return Err(vec![FieldError::new("version", "Missing, is this a file from `tts!export`?")]),
+    };
+
+    let mut errors = Vec::new();
+    for name in object.keys().filter(|name| !FIELDS.iter().any(|(field, added)| field == name && *added <= version)) {
+        errors.push(FieldError::new(name.as_str(), "Unknown field"));
+    }
+    let voice_channel = if version >= 2 { field::<Option<String>>(object, "voice_channel", &mut errors) } else { Some(None) };
+    let text_channel = field::<Option<String>>(object, "text_channel", &mut errors);
+    let dictionary = field::<BTreeMap<String, String>>(object, "dictionary", &mut errors);
+    let presets = list::<BackupPreset>(object, "presets", &mut errors);
+    let filter = field::<BackupFilter>(object, "filter", &mut errors);
+    let settings = if version >= 2 { field::<BTreeMap<String, String>>(object, "settings", &mut errors).map(Some) } else { Some(None) };
+    let users = list::<BackupUser>(object, "users", &mut errors);
+    let (voice_channel, text_channel, dictionary, presets, filter, settings, users) = match (voice_channel, text_channel, dictionary, presets, filter, settings, users) {
+        (Some(voice_channel), Some(text_channel), Some(dictionary), Some(presets), Some(filter), Some(settings), Some(users)) => {
+            (voice_channel, text_channel, dictionary, presets, filter, settings, users)
+        }
+        _ => return Err(errors),
+    };
+
+    for (name, channel) in [("voice_channel", &voice_channel), ("text_channel", &text_channel)] {
+        if channel.as_deref().is_some_and(|channel| parse_id(channel).is_none()) {
+            errors.push(FieldError::new(name, "Expected a channel id"));
+        }
+    }
+    for word in dictionary.keys() {
+        check_word(word, format!("dictionary.{}", word), &mut errors);
+    }
+    let mut names = Vec::new();
+    for (i, preset) in presets.iter().enumerate() {
+        let name = preset.name.to_lowercase();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            errors.push(FieldError::new(format!("presets[{}].name", i), "Preset names can only contain letters, numbers and underscores"));
+        } else if names.contains(&name) {
+            errors.push(FieldError::new(format!("presets[{}].name", i), format!("There is already a preset called `{}`", name)));
+        }
+        names.push(name);
+        if voices::find(&preset.voice).is_none() {
+            errors.push(FieldError::new(format!("presets[{}].voice", i), format!("Unknown voice `{}`", preset.voice)));
+        }
+        preset.options.check(&format!("presets[{}].options", i), &mut errors);
+    }
+    if let Err(e) = filter.policy.parse::<Policy>() {
+        errors.push(FieldError::new("filter.policy", e));
+    }
+    for (i, word) in filter.words.iter().enumerate() {
+        check_word(word, format!("filter.words[{}]", i), &mut errors);
+    }
+    for (name, value) in settings.iter().flatten() {
+        let path = format!("settings.{}", name);
+        match registry::find(name) {
+            Some(setting) if setting.levels().contains(&Level::Server) => {
+                if let Err(e) = setting.normalize(value) {
+                    errors.push(FieldError::new(path, e));
+                }
+            }
+            Some(_) => errors.push(FieldError::new(path, "Can't be set for a whole server")),
+            None => errors.push(FieldError::new(path, "Unknown setting")),
+        }
+    }
+    let mut ids = Vec::new();
+    for (i, user) in users.iter().enumerate() {
+        match parse_id(&user.id) {
+            Some(id) if ids.contains(&id) => errors.push(FieldError::new(format!("users[{}].id", i), "This user is in the file twice")),
+            Some(id) => ids.push(id),
+            None => errors.push(FieldError::new(format!("users[{}].id", i), "Expected a user id")),
+        }
+        if let Some(voice) = &user.voice {
+            // Users can also pick one of the presets
+            if voices::find(voice).is_none() && !names.contains(&voice.to_lowercase()) {
+                errors.push(FieldError::new(format!("users[{}].voice", i), format!("Unknown voice or preset `{}`", voice)));
+            }
+        }
+        if let Some(options) = &user.options {
+            options.check(&format!("users[{}].options", i), &mut errors);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Backup { version: VERSION, voice_channel, text_channel, dictionary, presets, filter, settings, users })
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    fn example() -> Backup {
+        Backup {
+            version: VERSION,
+            voice_channel: None,
+            text_channel: Some("123456789012345678".to_string()),
+            dictionary: [("brb".to_string(), "be right back".to_string())].into_iter().collect(),
+            presets: vec![BackupPreset {
+                name: "narrator".to_string(),
+                voice: "ttsmp3-Brian".to_string(),
+                options: Effects { rate: 0.9, ..Effects::default() }.into(),
+            }],
+            filter: BackupFilter {
+                enabled: true,
+                builtin: false,
+                policy: "replace".to_string(),
+                replacement: "beep".to_string(),
+                words: vec!["heck".to_string()],
+            },
+            settings: Some([("volume".to_string(), "80".to_string()), ("prefix".to_string(), "!".to_string())].into_iter().collect()),
+            users: vec![
+                BackupUser { id: "987654321098765432".to_string(), voice: Some("narrator".to_string()), options: None },
+                BackupUser { id: "42".to_string(), voice: None, options: Some(Effects::default().into()) },
+            ],
+        }
+    }
+
+    fn errors(value: Value) -> Vec<String> {
+        parse(value.to_string().as_bytes()).unwrap_err().into_iter().map(|e| e.field).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let backup = example();
+        let data = serde_json::to_vec_pretty(&backup).unwrap();
+        assert_eq!(parse(&data).unwrap(), backup);
+    }
+
+    #[test]
+    fn test_version() {
+        let mut value = serde_json::to_value(example()).unwrap();
+        value["version"] = Value::from(VERSION + 1);
+        assert_eq!(errors(value.clone()), vec!["version"]);
+        value.as_object_mut().unwrap().remove("version");
+        assert_eq!(errors(value), vec!["version"]);
+        assert_eq!(parse(b"not json").unwrap_err()[0].field, "file");
+    }
+
+    #[test]
+    fn test_version_1() {
+        let mut value = serde_json::to_value(example()).unwrap();
+        value["version"] = Value::from(1);
+        let object = value.as_object_mut().unwrap();
+        object.remove("voice_channel");
+        object.remove("settings");
+        let backup = parse(value.to_string().as_bytes()).unwrap();
+        assert_eq!(backup.settings, None);
+        assert_eq!(backup.users, example().users);
+        // Fields from later versions aren't read from older files
+        value["settings"] = Value::from(Map::new());
+        assert_eq!(errors(value), vec!["settings"]);
+    }
+
+    #[test]
+    fn test_field_errors() {
+        let mut value = serde_json::to_value(example()).unwrap();
+        value["presets"][0]["voice"] = Value::from("nobody");
+        value["presets"][0]["options"]["rate"] = Value::from(5.0);
+        value["filter"]["policy"] = Value::from("shout");
+        value["users"][0]["id"] = Value::from("me");
+        value["users"][1]["options"]["effect"] = Value::from("reverb");
+        value["dictionary"]["two words"] = Value::from("x");
+        value["voice_channel"] = Value::from("general");
+        value["settings"]["volume"] = Value::from("900");
+        value["settings"]["detect_language"] = Value::from("off");
+        value["settings"]["speed"] = Value::from("1");
+        value["extra"] = Value::from(true);
+        let mut fields = errors(value);
+        fields.sort();
+        assert_eq!(fields, vec![
+            "dictionary.two words",
+            "extra",
+            "filter.policy",
+            "presets[0].options.rate",
+            "presets[0].voice",
+            "settings.speed",
+            "settings.volume",
+            "users[0].id",
+            "users[1].options.effect",
+            "voice_channel",
+        ]);
+    }
+
+    #[test]
+    fn test_shape_errors() {
+        let mut value = serde_json::to_value(example()).unwrap();
+        value["users"][1] = Value::from("42");
+        value.as_object_mut().unwrap().remove("filter");
+        let mut fields = errors(value);
+        fields.sort();
+        assert_eq!(fields, vec!["filter", "users[1]"]);
+    }
+}
@@ -1,12 +1,20 @@
-use sqlx::Executor;
-use sqlx::SqlitePool;
-use sqlx::Connection;
+use std::collections::{BTreeMap, HashMap};
 use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::autocomplete::AutocompleteInteraction;
 use serenity::prelude::*;
 use serde::{Serialize, Deserialize};
 use lazy_static::lazy_static;
-use sqlx::query;
 use crate::Database;
+use crate::filter::{self, Policy};
+use crate::pipeline::Segment;
+use crate::spam::Limit;
+use crate::audio::Effects;
+use crate::voices;
+use crate::prefix::{self, Ignore};
+use crate::settings::{self as registry, Key, Level, Setting};
+use crate::backup::{self, Backup, BackupFilter, BackupPreset, BackupUser};
 
 // Let's make a macro to automate the tedious task of unwrapping the database context
 macro_rules! db {
@@ -16,111 +24,996 @@ macro_rules! db {
 }
 
 
-// Config structs for interacting with the SQLite database
+// Config structs for interacting with the database, SQLite or PostgreSQL (see storage.rs)
+
+/// Where a setting is read or written: the server (`None` in DMs), channel and user a command or message came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scope {
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub user_id: UserId,
+}
+
+impl Scope {
+    /// The server's id, or an error in DMs, which don't have per-server settings
+    pub fn server_id(&self) -> sqlx::Result<i64> {
+        self.guild_id
+            .map(|guild_id| guild_id.0 as i64)
+            .ok_or_else(|| sqlx::Error::Configuration("Server settings were looked up outside of a server".into()))
+    }
+}
+
+impl From<&Message> for Scope {
+    fn from(msg: &Message) -> Self {
+        Scope { guild_id: msg.guild_id, channel_id: msg.channel_id, user_id: msg.author.id }
+    }
+}
+
+impl From<&AutocompleteInteraction> for Scope {
+    fn from(interaction: &AutocompleteInteraction) -> Self {
+        Scope { guild_id: interaction.guild_id, channel_id: interaction.channel_id, user_id: interaction.user.id }
+    }
+}
+
+impl From<&ApplicationCommandInteraction> for Scope {
+    fn from(interaction: &ApplicationCommandInteraction) -> Self {
+        Scope { guild_id: interaction.guild_id, channel_id: interaction.channel_id, user_id: interaction.user.id }
+    }
+}
 
 pub struct Server {
     ctx: Context,
-    msg: Message,
+    scope: Scope,
     pub id: i64,
     pub voice_channel: Option<i64>,
     pub text_channel: Option<i64>,
 }
 
 impl Server {
-    pub async fn from_db(ctx: &Context, msg: &Message) -> Self {
-        // query
-        let database = db!(ctx);
-        let server_id = msg.guild_id.unwrap().0 as i64;
-        let server_query = query!("SELECT * FROM servers WHERE id = ?", server_id).fetch_one(&database).await;
-        if server_query.is_err() {
-            // Then it probably doesn't exist
-            // Add it to the database
-            query!("INSERT INTO servers (id) VALUES (?)", server_id).execute(&database).await.unwrap();
-            // Then return the defaults
-            return Server {
-                ctx: ctx.clone(),
-                msg: msg.clone(),
-                id: server_id,
-                voice_channel: None,
-                text_channel: None,
-            };
-        }
-        let server = server_query.unwrap();
-        Server {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let server_id = scope.server_id()?;
+        Server::register(ctx, server_id).await?;
+        let (voice_channel, text_channel) = db!(ctx).server_channels(server_id).await?;
+        Ok(Server {
             ctx: ctx.clone(),
-            msg: msg.clone(),
-            id: server.id,
-            voice_channel: server.voice_channel,
-            text_channel: server.text_channel,
-        }
+            scope,
+            id: server_id,
+            voice_channel,
+            text_channel,
+        })
     }
 
-    pub async fn update_voice_channel(mut self, channel_id: i64) -> Self {
-        let database = db!(&self.ctx);
-
-        let server_id = self.id;
-        query!("UPDATE servers SET voice_channel = ? WHERE id = ?", channel_id, server_id).execute(&database).await.unwrap();
+    /// Add a server if it's new, in one statement so concurrent messages can't add it twice
+    pub async fn register(ctx: &Context, server_id: i64) -> sqlx::Result<()> {
+        db!(ctx).register_server(server_id).await
+    }
 
+    pub async fn update_voice_channel(mut self, channel_id: i64) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_voice_channel(self.id, channel_id).await?;
         self.voice_channel = Some(channel_id);
-        self
+        Ok(self)
     }
 
-    pub async fn update_text_channel(mut self, channel_id: i64) -> Self {
-        let database = db!(&self.ctx);
-
-        let server_id = self.id;
-        query!("UPDATE servers SET text_channel = ? WHERE id = ?", channel_id, server_id).execute(&database).await.unwrap();
+    pub async fn update_text_channel(mut self, channel_id: i64) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_text_channel(self.id, channel_id).await?;
         self.text_channel = Some(channel_id);
-        self
+        Ok(self)
     }
 }
 
 pub struct User {
     ctx: Context,
-    msg: Message,
+    scope: Scope,
     pub id: i64,
     pub server_id: i64,
     pub voice: String,
+    pub effects: Effects,
 }
 
 impl User {
-    pub async fn from_db(ctx: &Context, msg: &Message) -> Self {
-        // query
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        User::from_settings(&Settings::from_db(ctx, scope).await?).await
+    }
+
+    /// The user of an already loaded scope, whose settings give the default voice
+    pub async fn from_settings(settings: &Settings) -> sqlx::Result<Self> {
+        let ctx = &settings.ctx;
+        let scope = settings.scope;
         let database = db!(ctx);
-        let user_id = msg.author.id.0 as i64;
-        let server_id = msg.guild_id.unwrap().0 as i64;
-        let user_query = query!("SELECT * FROM users WHERE id = ? AND server_id = ?", user_id, server_id).fetch_one(&database).await;
-        if user_query.is_err() {
-            // Then it probably doesn't exist
-            // Add it to the database
-            let voice = "tiktok-en_us_002";
-            query!("INSERT INTO users (id, server_id, voice) VALUES (?, ?, ?)", user_id, server_id, voice).execute(&database).await.unwrap();
-            // Then return the defaults
-            User {
-                ctx: ctx.clone(),
-                msg: msg.clone(),
-                id: user_id,
-                server_id: server_id,
-                voice: voice.to_string(),
-            }
-        } else {
-            let user = user_query.unwrap();
-            User {
-                ctx: ctx.clone(),
-                msg: msg.clone(),
-                id: user.id.unwrap(),
-                server_id: user.server_id.unwrap(),
-                voice: user.voice.unwrap(),
-            }
-        }
+        let user_id = scope.user_id.0 as i64;
+        let server_id = scope.server_id()?;
+        // New users start without a voice, so they follow their global or the server's default voice
+        let voice = database.user_voice(user_id, server_id).await?;
+        let options = database.voice_options(user_id, server_id).await?;
+        // Anything the user didn't set in this server comes from their global profile
+        let profile = Profile::from_db(ctx, scope).await?;
+        let effects = options.or(profile.effects).unwrap_or_default();
+        let voice = voice.or(profile.voice).unwrap_or_else(|| settings.get(&registry::VOICE));
+        Ok(User {
+            ctx: ctx.clone(),
+            scope,
+            id: user_id,
+            server_id,
+            voice,
+            effects,
+        })
     }
 
-    pub async fn update_voice(mut self, voice: &str) -> Self {
-        let database = db!(&self.ctx);
-        let user_id = self.id;
-        let server_id = self.server_id;
-        query!("UPDATE users SET voice = ? WHERE id = ? AND server_id = ?", voice, user_id, server_id).execute(&database).await.unwrap();
+    pub async fn update_voice(mut self, voice: &str) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_user_voice(self.id, self.server_id, Some(voice)).await?;
         self.voice = voice.to_string();
-        self
+        Ok(self)
+    }
+
+    /// Forget the user's voice in this server, so they follow their global or the server's default voice again
+    pub async fn reset_voice(mut self) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_user_voice(self.id, self.server_id, None).await?;
+        self.voice = match Profile::from_db(&self.ctx, self.scope).await?.voice {
+            Some(voice) => voice,
+            None => Settings::from_db(&self.ctx, self.scope).await?.get(&registry::VOICE),
+        };
+        Ok(self)
+    }
+
+    pub async fn update_effects(mut self, effects: Effects) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_voice_options(self.id, self.server_id, &effects).await?;
+        self.effects = effects;
+        Ok(self)
+    }
+
+    /// Forget the user's voice options in this server, so their global ones are used again
+    pub async fn reset_effects(mut self) -> sqlx::Result<Self> {
+        db!(&self.ctx).remove_voice_options(self.id, self.server_id).await?;
+        self.effects = Profile::from_db(&self.ctx, self.scope).await?.effects.unwrap_or_default();
+        Ok(self)
+    }
+}
+
+/// A user's settings in every server, used wherever they haven't set something for that server.
+/// Works in DMs, unlike the per-server structs.
+pub struct Profile {
+    ctx: Context,
+    scope: Scope,
+    pub id: i64,
+    pub voice: Option<String>,
+    pub effects: Option<Effects>,
+    // What the bot calls the user when they are mentioned
+    pub alias: Option<String>,
+}
+
+impl Profile {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let id = scope.user_id.0 as i64;
+        let (voice, effects, alias) = db!(ctx).profile(id).await?;
+        Ok(Profile {
+            ctx: ctx.clone(),
+            scope,
+            id,
+            voice,
+            effects,
+            alias,
+        })
+    }
+
+    /// Aliases of several users at once, for reading mentions
+    pub async fn aliases(ctx: &Context, ids: &[i64]) -> sqlx::Result<HashMap<i64, String>> {
+        db!(ctx).aliases(ids).await
+    }
+
+    async fn save(&self) -> sqlx::Result<()> {
+        db!(&self.ctx).save_profile(self.id, self.voice.as_deref(), self.effects, self.alias.as_deref()).await
+    }
+
+    pub async fn update_voice(mut self, voice: Option<&str>) -> sqlx::Result<Self> {
+        self.voice = voice.map(str::to_string);
+        self.save().await?;
+        Ok(self)
+    }
+
+    pub async fn update_effects(mut self, effects: Option<Effects>) -> sqlx::Result<Self> {
+        self.effects = effects;
+        self.save().await?;
+        Ok(self)
+    }
+
+    pub async fn update_alias(mut self, alias: Option<&str>) -> sqlx::Result<Self> {
+        self.alias = alias.map(str::to_string);
+        self.save().await?;
+        Ok(self)
+    }
+}
+
+pub struct Dictionary {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    // Lowercase word -> replacement
+    pub entries: HashMap<String, String>,
+}
+
+impl Dictionary {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let server_id = scope.server_id()?;
+        let rows = db!(ctx).dictionary(server_id).await?;
+        Ok(Dictionary {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            entries: rows.into_iter().collect(),
+        })
+    }
+
+    pub async fn add(mut self, word: &str, replacement: &str) -> sqlx::Result<Self> {
+        let word = word.to_lowercase();
+        db!(&self.ctx).add_dictionary_entry(self.server_id, &word, replacement).await?;
+        self.entries.insert(word, replacement.to_string());
+        Ok(self)
+    }
+
+    pub async fn remove(mut self, word: &str) -> sqlx::Result<Self> {
+        let word = word.to_lowercase();
+        db!(&self.ctx).remove_dictionary_entry(self.server_id, &word).await?;
+        self.entries.remove(&word);
+        Ok(self)
+    }
+}
+
+pub struct Filter {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    pub enabled: bool,
+    // Whether the built-in word list is used on top of `words`
+    pub builtin: bool,
+    pub policy: Policy,
+    pub replacement: String,
+    pub words: Vec<String>,
+}
+
+impl Filter {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let database = db!(ctx);
+        let server_id = scope.server_id()?;
+        let (enabled, builtin, policy, replacement) = database.filter(server_id).await?
+            .unwrap_or((false, true, Policy::Bleep.to_string(), "beep".to_string()));
+        let words = database.filter_words(server_id).await?;
+        Ok(Filter {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            enabled,
+            builtin,
+            policy: policy.parse().unwrap_or(Policy::Bleep),
+            replacement,
+            words,
+        })
+    }
+
+    /// Apply the filter to a message, see `filter::apply`
+    pub fn apply(&self, text: &str) -> Option<Vec<Segment>> {
+        if !self.enabled {
+            return Some(vec![Segment::Speech(text.to_string())]);
+        }
+        filter::apply(text, &self.words, self.builtin, self.policy, &self.replacement)
+    }
+
+    async fn save(&self) -> sqlx::Result<()> {
+        db!(&self.ctx).save_filter(self.server_id, self.enabled, self.builtin, self.policy, &self.replacement).await
+    }
+
+    pub async fn update_enabled(mut self, enabled: bool) -> sqlx::Result<Self> {
+        self.enabled = enabled;
+        self.save().await?;
+        Ok(self)
+    }
+
+    pub async fn update_builtin(mut self, builtin: bool) -> sqlx::Result<Self> {
+        self.builtin = builtin;
+        self.save().await?;
+        Ok(self)
+    }
+
+    pub async fn update_policy(mut self, policy: Policy, replacement: Option<&str>) -> sqlx::Result<Self> {
+        self.policy = policy;
+        if let Some(replacement) = replacement {
+            self.replacement = replacement.to_string();
+        }
+        self.save().await?;
+        Ok(self)
+    }
+
+    pub async fn add_word(mut self, word: &str) -> sqlx::Result<Self> {
+        let word = word.to_lowercase();
+        db!(&self.ctx).add_filter_word(self.server_id, &word).await?;
+        if !self.words.contains(&word) {
+            self.words.push(word);
+        }
+        Ok(self)
+    }
+
+    pub async fn remove_word(mut self, word: &str) -> sqlx::Result<Self> {
+        let word = word.to_lowercase();
+        db!(&self.ctx).remove_filter_word(self.server_id, &word).await?;
+        self.words.retain(|w| *w != word);
+        Ok(self)
+    }
+}
+
+pub struct SpamSettings {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    pub user_limit: Limit,
+    pub server_limit: Limit,
+    // Maximum number of characters read from a message, zero for no limit
+    pub max_length: u32,
+    // Whether repeated characters and words are collapsed
+    pub collapse: bool,
+}
+
+impl SpamSettings {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        SpamSettings::from_settings(&Settings::from_db(ctx, scope).await?).await
+    }
+
+    pub async fn from_settings(settings: &Settings) -> sqlx::Result<Self> {
+        let ctx = &settings.ctx;
+        let scope = settings.scope;
+        let server_id = scope.server_id()?;
+        // Defaults match the schema
+        let (user_limit, server_limit) = db!(ctx).spam_limits(server_id).await?
+            .unwrap_or((Limit { messages: 5, seconds: 10 }, Limit { messages: 20, seconds: 10 }));
+        Ok(SpamSettings {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            user_limit,
+            server_limit,
+            max_length: settings.get(&registry::MAX_LENGTH),
+            collapse: settings.get(&registry::COLLAPSE),
+        })
+    }
+
+    async fn save(&self) -> sqlx::Result<()> {
+        db!(&self.ctx).save_spam_limits(self.server_id, self.user_limit, self.server_limit).await
+    }
+
+    pub async fn update_user_limit(mut self, limit: Limit) -> sqlx::Result<Self> {
+        self.user_limit = limit;
+        self.save().await?;
+        Ok(self)
+    }
+
+    pub async fn update_server_limit(mut self, limit: Limit) -> sqlx::Result<Self> {
+        self.server_limit = limit;
+        self.save().await?;
+        Ok(self)
+    }
+
+    pub async fn update_max_length(mut self, max_length: u32) -> sqlx::Result<Self> {
+        Settings::from_db(&self.ctx, self.scope).await?
+            .set(Level::Server, registry::MAX_LENGTH.name, &registry::MAX_LENGTH.show(&max_length)).await?;
+        self.max_length = max_length;
+        Ok(self)
+    }
+
+    pub async fn update_collapse(mut self, collapse: bool) -> sqlx::Result<Self> {
+        Settings::from_db(&self.ctx, self.scope).await?
+            .set(Level::Server, registry::COLLAPSE.name, &registry::COLLAPSE.show(&collapse)).await?;
+        self.collapse = collapse;
+        Ok(self)
+    }
+}
+
+pub struct LanguageVoices {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    pub user_id: i64,
+    // Language -> voice, for the message author and for the whole server
+    pub user: HashMap<String, String>,
+    pub server: HashMap<String, String>,
+}
+
+impl LanguageVoices {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let server_id = scope.server_id()?;
+        let user_id = scope.user_id.0 as i64;
+        let rows = db!(ctx).language_voices(server_id, user_id).await?;
+        let mut user = HashMap::new();
+        let mut server = HashMap::new();
+        for (id, language, voice) in rows {
+            if id == 0 { server.insert(language, voice) } else { user.insert(language, voice) };
+        }
+        Ok(LanguageVoices {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            user_id,
+            user,
+            server,
+        })
+    }
+
+    /// Voice to use for a language, preferring the user's own mapping over the server's
+    pub fn resolve(&self, language: &str) -> Option<&str> {
+        self.user.get(language).or_else(|| self.server.get(language)).map(|v| v.as_str())
+    }
+
+    async fn set(&self, user_id: i64, language: &str, voice: Option<&str>) -> sqlx::Result<()> {
+        db!(&self.ctx).set_language_voice(self.server_id, user_id, language, voice).await
+    }
+
+    /// Set or clear (with `None`) the author's voice for a language
+    pub async fn update_user(mut self, language: &str, voice: Option<&str>) -> sqlx::Result<Self> {
+        self.set(self.user_id, language, voice).await?;
+        match voice {
+            Some(voice) => self.user.insert(language.to_string(), voice.to_string()),
+            None => self.user.remove(language),
+        };
+        Ok(self)
+    }
+
+    /// Set or clear (with `None`) the server-wide voice for a language
+    pub async fn update_server(mut self, language: &str, voice: Option<&str>) -> sqlx::Result<Self> {
+        self.set(0, language, voice).await?;
+        match voice {
+            Some(voice) => self.server.insert(language.to_string(), voice.to_string()),
+            None => self.server.remove(language),
+        };
+        Ok(self)
+    }
+}
+
+pub struct AudioSettings {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    // Loudness every message is normalized to in LUFS, or `None` to leave it as the backend returned it
+    pub loudness: Option<f32>,
+    // Master volume, 1.0 is 100%
+    pub volume: f32,
+}
+
+impl AudioSettings {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        AudioSettings::from_settings(&Settings::from_db(ctx, scope).await?)
+    }
+
+    pub fn from_settings(settings: &Settings) -> sqlx::Result<Self> {
+        let scope = settings.scope;
+        let server_id = scope.server_id()?;
+        Ok(AudioSettings {
+            ctx: settings.ctx.clone(),
+            scope,
+            server_id,
+            loudness: settings.get(&registry::LOUDNESS),
+            volume: settings.get(&registry::VOLUME) as f32 / 100.0,
+        })
+    }
+
+    pub async fn update_loudness(mut self, loudness: Option<f32>) -> sqlx::Result<Self> {
+        Settings::from_db(&self.ctx, self.scope).await?
+            .set(Level::Server, registry::LOUDNESS.name, &registry::LOUDNESS.show(&loudness)).await?;
+        self.loudness = loudness;
+        Ok(self)
+    }
+
+    pub async fn update_volume(mut self, volume: f32) -> sqlx::Result<Self> {
+        let percent = (volume * 100.0).round() as u32;
+        Settings::from_db(&self.ctx, self.scope).await?
+            .set(Level::Server, registry::VOLUME.name, &registry::VOLUME.show(&percent)).await?;
+        self.volume = volume;
+        Ok(self)
+    }
+}
+
+/// A named voice and set of effects defined by a server's admins, usable with `tts!setvoice <name>`
+#[derive(Debug, Clone)]
+pub struct Preset {
+    pub name: String,
+    pub voice: String,
+    pub effects: Effects,
+}
+
+pub struct Presets {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    // Lowercase name -> preset
+    pub presets: HashMap<String, Preset>,
+}
+
+impl Presets {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let server_id = scope.server_id()?;
+        let presets = db!(ctx).presets(server_id).await?.into_iter()
+            .map(|(name, voice, effects)| (name.clone(), Preset { name, voice, effects }))
+            .collect();
+        Ok(Presets {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            presets,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.get(&name.to_lowercase())
+    }
+
+    /// Turn a stored voice, which may be a preset name, into a concrete voice id and effects.
+    /// The user's own effects are stacked on top of the preset's.
+    pub fn resolve(&self, voice: &str, effects: &Effects, default_voice: &str) -> (String, Effects) {
+        match self.get(voice) {
+            Some(preset) => (preset.voice.clone(), preset.effects.combine(effects)),
+            // A preset that has since been removed
+            None if voices::find(voice).is_none() && !voice.contains('-') => (default_voice.to_string(), *effects),
+            None => (voice.to_string(), *effects),
+        }
+    }
+
+    pub async fn add(mut self, name: &str, voice: &str, effects: Effects) -> sqlx::Result<Self> {
+        let name = name.to_lowercase();
+        db!(&self.ctx).add_preset(self.server_id, &name, voice, &effects).await?;
+        self.presets.insert(name.clone(), Preset { name, voice: voice.to_string(), effects });
+        Ok(self)
+    }
+
+    pub async fn remove(mut self, name: &str) -> sqlx::Result<Self> {
+        let name = name.to_lowercase();
+        db!(&self.ctx).remove_preset(self.server_id, &name).await?;
+        self.presets.remove(&name);
+        Ok(self)
+    }
+}
+
+/// Which voices can be used in a server, and the voice users get until they pick one
+pub struct VoicePolicy {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    pub default_voice: String,
+    // If true only the listed providers and voices can be used, otherwise they are the ones that can't
+    pub allowlist: bool,
+    // Lowercase provider names and voice ids
+    pub entries: Vec<String>,
+}
+
+impl VoicePolicy {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        VoicePolicy::from_settings(&Settings::from_db(ctx, scope).await?).await
+    }
+
+    pub async fn from_settings(settings: &Settings) -> sqlx::Result<Self> {
+        let ctx = &settings.ctx;
+        let scope = settings.scope;
+        let database = db!(ctx);
+        let server_id = scope.server_id()?;
+        let allowlist = database.voice_allowlist(server_id).await?.unwrap_or(false);
+        let entries = database.voice_rules(server_id).await?;
+        Ok(VoicePolicy {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            default_voice: settings.get(&registry::VOICE),
+            allowlist,
+            entries,
+        })
+    }
+
+    /// Whether a voice id can be used in this server
+    pub fn allows(&self, voice: &str) -> bool {
+        let voice = voice.to_lowercase();
+        let provider = voice.split('-').next().unwrap_or_default();
+        let listed = self.entries.iter().any(|entry| *entry == voice || entry == provider);
+        listed == self.allowlist
+    }
+
+    /// The voice to actually read with: the voice itself if it's allowed, otherwise the server's default voice,
+    /// otherwise the first allowed voice in the catalog. `None` if nothing is allowed.
+    pub fn enforce(&self, voice: &str) -> Option<String> {
+        if self.allows(voice) {
+            return Some(voice.to_string());
+        }
+        if self.allows(&self.default_voice) {
+            return Some(self.default_voice.clone());
+        }
+        voices::CATALOG.iter().find(|v| self.allows(v.id)).map(|v| v.id.to_string())
+    }
+
+    pub async fn update_default_voice(mut self, voice: &str) -> sqlx::Result<Self> {
+        Settings::from_db(&self.ctx, self.scope).await?
+            .set(Level::Server, registry::VOICE.name, voice).await?;
+        self.default_voice = voice.to_string();
+        Ok(self)
+    }
+
+    pub async fn update_allowlist(mut self, allowlist: bool) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_voice_allowlist(self.server_id, allowlist).await?;
+        self.allowlist = allowlist;
+        Ok(self)
+    }
+
+    pub async fn add_entry(mut self, entry: &str) -> sqlx::Result<Self> {
+        let entry = entry.to_lowercase();
+        db!(&self.ctx).add_voice_rule(self.server_id, &entry).await?;
+        if !self.entries.contains(&entry) {
+            self.entries.push(entry);
+        }
+        Ok(self)
+    }
+
+    pub async fn remove_entry(mut self, entry: &str) -> sqlx::Result<Self> {
+        let entry = entry.to_lowercase();
+        db!(&self.ctx).remove_voice_rule(self.server_id, &entry).await?;
+        self.entries.retain(|e| *e != entry);
+        Ok(self)
+    }
+}
+
+/// Whether a user agreed to have their messages read, which sends them to the TTS backends
+pub struct Consent {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    pub user_id: i64,
+    // Set with tts!optin/optout in this server, `None` if the user never did
+    pub server: Option<bool>,
+    // Opted out of every server, in DMs or with --global
+    pub global_optout: bool,
+    // Only read users who opted in
+    pub optin_only: bool,
+}
+
+impl Consent {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let database = db!(ctx);
+        // Server 0 holds the global choice, so DMs work too
+        let server_id = scope.guild_id.map_or(0, |id| id.0 as i64);
+        let user_id = scope.user_id.0 as i64;
+        let rows = database.consent(user_id, server_id).await?;
+        let optin_only = database.optin_only(server_id).await?;
+        Ok(Consent {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            user_id,
+            server: rows.iter().find(|(id, _)| *id == server_id && server_id != 0).map(|(_, opted_in)| *opted_in),
+            global_optout: rows.iter().any(|(id, opted_in)| *id == 0 && !opted_in),
+            optin_only: optin_only.unwrap_or(false),
+        })
+    }
+
+    /// Whether the user's messages can be read in this server
+    pub fn allows_reading(&self) -> bool {
+        !self.global_optout && self.server.unwrap_or(!self.optin_only)
+    }
+
+    async fn set(&self, server_id: i64, opted_in: Option<bool>) -> sqlx::Result<()> {
+        db!(&self.ctx).set_consent(server_id, self.user_id, opted_in).await
+    }
+
+    pub async fn update_server(mut self, opted_in: bool) -> sqlx::Result<Self> {
+        self.set(self.server_id, Some(opted_in)).await?;
+        self.server = Some(opted_in);
+        Ok(self)
+    }
+
+    pub async fn update_global_optout(mut self, optout: bool) -> sqlx::Result<Self> {
+        self.set(0, if optout { Some(false) } else { None }).await?;
+        self.global_optout = optout;
+        Ok(self)
+    }
+
+    pub async fn update_optin_only(mut self, optin_only: bool) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_optin_only(self.server_id, optin_only).await?;
+        self.optin_only = optin_only;
+        Ok(self)
+    }
+}
+
+/// Someone who can be blocked from being read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    User(i64),
+    Role(i64),
+}
+
+impl Target {
+    fn kind(&self) -> &'static str {
+        match self {
+            Target::User(_) => "user",
+            Target::Role(_) => "role",
+        }
+    }
+
+    fn id(&self) -> i64 {
+        match self {
+            Target::User(id) | Target::Role(id) => *id,
+        }
+    }
+}
+
+/// Users and roles that are never read, and the role needed to be read at all
+pub struct Access {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    pub blocked: Vec<Target>,
+    // If set, only members with this role are read
+    pub tts_role: Option<i64>,
+}
+
+impl Access {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let database = db!(ctx);
+        let server_id = scope.server_id()?;
+        let rows = database.blocks(server_id).await?;
+        let tts_role = database.tts_role(server_id).await?;
+        Ok(Access {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            blocked: rows.into_iter()
+                .map(|(kind, id)| if kind == "role" { Target::Role(id) } else { Target::User(id) })
+                .collect(),
+            tts_role,
+        })
+    }
+
+    /// Whether a member with these roles can be read
+    pub fn allows(&self, user_id: i64, roles: &[i64]) -> bool {
+        let blocked = self.blocked.iter().any(|target| match target {
+            Target::User(id) => *id == user_id,
+            Target::Role(id) => roles.contains(id),
+        });
+        !blocked && self.tts_role.map_or(true, |role| roles.contains(&role))
+    }
+
+    pub async fn block(mut self, target: Target) -> sqlx::Result<Self> {
+        db!(&self.ctx).add_block(self.server_id, target.kind(), target.id()).await?;
+        if !self.blocked.contains(&target) {
+            self.blocked.push(target);
+        }
+        Ok(self)
+    }
+
+    pub async fn unblock(mut self, target: Target) -> sqlx::Result<Self> {
+        db!(&self.ctx).remove_block(self.server_id, target.kind(), target.id()).await?;
+        self.blocked.retain(|t| *t != target);
+        Ok(self)
+    }
+
+    pub async fn update_tts_role(mut self, role: Option<i64>) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_tts_role(self.server_id, role).await?;
+        self.tts_role = role;
+        Ok(self)
+    }
+}
+
+pub struct PermissionSettings {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    // Members with this role can run every command except picking the DJ role
+    pub dj_role: Option<i64>,
+}
+
+impl PermissionSettings {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let server_id = scope.server_id()?;
+        let dj_role = db!(ctx).dj_role(server_id).await?;
+        Ok(PermissionSettings {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            dj_role,
+        })
+    }
+
+    pub async fn update_dj_role(mut self, role: Option<i64>) -> sqlx::Result<Self> {
+        db!(&self.ctx).update_dj_role(self.server_id, role).await?;
+        self.dj_role = role;
+        Ok(self)
+    }
+}
+
+/// The server's command prefix, and other bots' commands that shouldn't be read
+pub struct Prefixes {
+    ctx: Context,
+    scope: Scope,
+    pub server_id: i64,
+    pub prefix: String,
+    pub ignored: Vec<Ignore>,
+}
+
+impl Prefixes {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        Prefixes::from_settings(&Settings::from_db(ctx, scope).await?).await
+    }
+
+    pub async fn from_settings(settings: &Settings) -> sqlx::Result<Self> {
+        let ctx = &settings.ctx;
+        let scope = settings.scope;
+        let server_id = scope.server_id()?;
+        let ignored = db!(ctx).ignored_prefixes(server_id).await?;
+        Ok(Prefixes {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            prefix: settings.get(&registry::PREFIX),
+            // Entries were validated when they were added
+            ignored: ignored.into_iter().filter_map(|entry| entry.parse().ok()).collect(),
+        })
+    }
+
+    /// Whether a message is a command rather than something to read, see `prefix::is_ignored`
+    pub fn ignores(&self, content: &str) -> bool {
+        prefix::is_ignored(content, &self.prefix, &self.ignored)
+    }
+
+    pub async fn update_prefix(mut self, prefix: &str) -> sqlx::Result<Self> {
+        Settings::from_db(&self.ctx, self.scope).await?
+            .set(Level::Server, registry::PREFIX.name, prefix).await?;
+        self.prefix = prefix.to_string();
+        Ok(self)
     }
-}
\ No newline at end of file
+
+    pub async fn add_ignored(mut self, ignore: Ignore) -> sqlx::Result<Self> {
+        db!(&self.ctx).add_ignored_prefix(self.server_id, &ignore.to_string()).await?;
+        if !self.ignored.contains(&ignore) {
+            self.ignored.push(ignore);
+        }
+        Ok(self)
+    }
+
+    pub async fn remove_ignored(mut self, ignore: &Ignore) -> sqlx::Result<Self> {
+        db!(&self.ctx).remove_ignored_prefix(self.server_id, &ignore.to_string()).await?;
+        self.ignored.retain(|i| i != ignore);
+        Ok(self)
+    }
+}
+
+/// Values of the settings in `settings::REGISTRY` at every level that applies to a scope
+pub struct Settings {
+    ctx: Context,
+    scope: Scope,
+    // 0 in DMs
+    pub server_id: i64,
+    values: HashMap<(Level, String), String>,
+}
+
+impl Settings {
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let server_id = scope.guild_id.map_or(0, |guild_id| guild_id.0 as i64);
+        let mut settings = Settings {
+            ctx: ctx.clone(),
+            scope,
+            server_id,
+            values: HashMap::new(),
+        };
+        let targets = [Level::User, Level::Channel, Level::Server].map(|level| {
+            let (server_id, target_id) = settings.target(level);
+            (level, server_id, target_id)
+        });
+        let rows = db!(ctx).settings(targets).await?;
+        settings.values = rows.into_iter()
+            .filter_map(|(level, key, value)| Some(((level.parse().ok()?, key), value)))
+            .collect();
+        Ok(settings)
+    }
+
+    /// The server and target a setting at `level` is stored under.
+    /// A user's settings follow them to every server, so they are the same in DMs and servers.
+    fn target(&self, level: Level) -> (i64, i64) {
+        match level {
+            Level::User => (0, self.scope.user_id.0 as i64),
+            Level::Channel => (self.server_id, self.scope.channel_id.0 as i64),
+            Level::Server => (self.server_id, self.server_id),
+            Level::Global => (0, 0),
+        }
+    }
+
+    /// The value set at a level, if any
+    pub fn value(&self, level: Level, name: &str) -> Option<&str> {
+        self.values.get(&(level, name.to_string())).map(String::as_str)
+    }
+
+    /// The effective value of a setting, from the most specific level that has one
+    pub fn get<T>(&self, key: &Key<T>) -> T {
+        key.resolve(|level| self.value(level, key.name))
+    }
+
+    /// The effective value of any setting, as it is stored
+    pub fn effective(&self, setting: &dyn Setting) -> String {
+        setting.effective(&|level| self.value(level, setting.name()))
+    }
+
+    /// Store an already validated value, see `Setting::normalize`
+    pub async fn set(mut self, level: Level, name: &str, value: &str) -> sqlx::Result<Self> {
+        let (server_id, target_id) = self.target(level);
+        db!(&self.ctx).set_setting(level, server_id, target_id, name, value).await?;
+        self.values.insert((level, name.to_string()), value.to_string());
+        Ok(self)
+    }
+
+    pub async fn reset(mut self, level: Level, name: &str) -> sqlx::Result<Self> {
+        let (server_id, target_id) = self.target(level);
+        db!(&self.ctx).remove_setting(level, server_id, target_id, name).await?;
+        self.values.remove(&(level, name.to_string()));
+        Ok(self)
+    }
+}
+
+impl Backup {
+    /// Everything `tts!export` saves about a server
+    pub async fn from_db(ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<Self> {
+        let scope = scope.into();
+        let database = db!(ctx);
+        let server = Server::from_db(ctx, scope).await?;
+        let dictionary = Dictionary::from_db(ctx, scope).await?;
+        let presets = Presets::from_db(ctx, scope).await?;
+        let filter = Filter::from_db(ctx, scope).await?;
+        let values = Settings::from_db(ctx, scope).await?;
+        // Only what users set in this server, not what they inherit from their global profile
+        let user_voices = database.user_voices(server.id).await?;
+        let options = database.user_voice_options(server.id).await?;
+        let mut users = BTreeMap::new();
+        // A preset that has since been removed reads as the default voice, and wouldn't import
+        for (id, voice) in user_voices.into_iter().filter(|(_, voice)| voices::find(voice).is_some() || presets.get(voice).is_some()) {
+            users.entry(id).or_insert(BackupUser { id: id.to_string(), voice: None, options: None }).voice = Some(voice);
+        }
+        for (id, effects) in options {
+            users.entry(id).or_insert(BackupUser { id: id.to_string(), voice: None, options: None }).options = Some(effects.into());
+        }
+
+        let mut presets = presets.presets.into_values()
+            .map(|preset| BackupPreset { name: preset.name, voice: preset.voice, options: preset.effects.into() })
+            .collect::<Vec<BackupPreset>>();
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut words = filter.words;
+        words.sort();
+        // Only what was set for the whole server, channels can't be carried over
+        let settings = registry::REGISTRY.iter()
+            .filter(|setting| setting.levels().contains(&Level::Server))
+            .filter_map(|setting| {
+                let value = values.value(Level::Server, setting.name())?;
+                Some((setting.name().to_string(), setting.normalize(value).ok()?))
+            })
+            .collect();
+        Ok(Backup {
+            version: backup::VERSION,
+            voice_channel: server.voice_channel.map(|channel| channel.to_string()),
+            text_channel: server.text_channel.map(|channel| channel.to_string()),
+            dictionary: dictionary.entries.into_iter().collect(),
+            presets,
+            filter: BackupFilter {
+                enabled: filter.enabled,
+                builtin: filter.builtin,
+                policy: filter.policy.to_string(),
+                replacement: filter.replacement,
+                words,
+            },
+            settings: Some(settings),
+            users: users.into_values().collect(),
+        })
+    }
+
+    /// Replace the server's dictionary, presets, filter and settings with the file's, and set the voices of the users in it.
+    /// Users who aren't in the file keep theirs. Everything is written at once, or nothing is if a query fails.
+    pub async fn restore(&self, ctx: &Context, scope: impl Into<Scope>) -> sqlx::Result<()> {
+        let scope = scope.into();
+        db!(ctx).restore_backup(scope.server_id()?, self).await
+    }
+}
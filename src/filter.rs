@@ -0,0 +1,128 @@
+// Content filter for spoken output.
+// Runs after normalization, so expanded slang ("wtf") is caught as well.
+
+use std::fmt;
+use std::str::FromStr;
+use crate::pipeline::Segment;
+
+/// Built-in list of words filtered when a server enables it.
+/// Suffixed forms ("-s", "-ing", "-ed", ...) are matched as well.
+pub static BUILTIN_WORDS: &[&str] = &[
+    "fuck", "fucker", "motherfucker", "shit", "bullshit", "bitch", "bastard", "asshole",
+    "dick", "dickhead", "cock", "cunt", "pussy", "twat", "wanker", "prick", "slut", "whore",
+    "damn", "goddamn", "piss", "bollocks", "crap", "douche", "douchebag", "jackass",
+];
+
+const SUFFIXES: &[&str] = &["", "s", "es", "ed", "er", "ers", "ing", "in", "y"];
+
+/// What to do with a message that contains a filtered word.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Don't read the message at all
+    Skip,
+    /// Replace the word with a tone
+    Bleep,
+    /// Replace the word with the server's replacement word
+    Replace,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(Policy::Skip),
+            "bleep" => Ok(Policy::Bleep),
+            "replace" => Ok(Policy::Replace),
+            _ => Err(format!("Unknown filter policy `{}`, expected `skip`, `bleep` or `replace`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Policy::Skip => write!(f, "skip"),
+            Policy::Bleep => write!(f, "bleep"),
+            Policy::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+fn is_filtered(word: &str, words: &[String], builtin: bool) -> bool {
+    // Checked piece by piece, so punctuation or tags around a word can't hide it
+    word.split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .any(|part| {
+            let part = part.to_lowercase();
+            let matches = |base: &str| SUFFIXES.iter().any(|suffix| part.strip_suffix(suffix) == Some(base));
+            words.iter().any(|w| matches(w)) || (builtin && BUILTIN_WORDS.iter().any(|w| matches(w)))
+        })
+}
+
+/// Apply the filter to a piece of text.
+/// Returns `None` if the message should not be read, otherwise the segments to synthesize.
+pub fn apply(text: &str, words: &[String], builtin: bool, policy: Policy, replacement: &str) -> Option<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for word in text.split_whitespace() {
+        if !is_filtered(word, words, builtin) {
+            current.push(word);
+            continue;
+        }
+        match policy {
+            Policy::Skip => return None,
+            Policy::Replace => current.push(replacement),
+            Policy::Bleep => {
+                if !current.is_empty() {
+                    segments.push(Segment::Speech(current.join(" ")));
+                    current.clear();
+                }
+                segments.push(Segment::Bleep);
+            }
+        }
+    }
+    if !current.is_empty() {
+        segments.push(Segment::Speech(current.join(" ")));
+    }
+    Some(segments)
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+    use crate::markup;
+
+    #[test]
+    fn test_policies() {
+        let words = vec!["heck".to_string()];
+        assert_eq!(apply("oh heck, no", &words, false, Policy::Skip, "beep"), None);
+        assert_eq!(
+            apply("oh HECKING no", &words, false, Policy::Replace, "beep"),
+            Some(vec![Segment::Speech("oh beep no".to_string())])
+        );
+        assert_eq!(
+            apply("oh shit no", &words, true, Policy::Bleep, "beep"),
+            Some(vec![Segment::Speech("oh".to_string()), Segment::Bleep, Segment::Speech("no".to_string())])
+        );
+        // Only whole words are filtered
+        assert_eq!(
+            apply("a classic scrap", &[], true, Policy::Skip, "beep"),
+            Some(vec![Segment::Speech("a classic scrap".to_string())])
+        );
+        // Tags around a word, on the allowlist or not, can't hide it
+        let segments = markup::parse("oh <emphasis>heck</emphasis> <b>heck</b> no")
+            .into_iter()
+            .flat_map(|segment| match segment {
+                Segment::Speech(text) => apply(&text, &words, false, Policy::Bleep, "beep").unwrap(),
+                segment => vec![segment],
+            })
+            .map(|segment| match segment {
+                Segment::Speech(text) => text,
+                Segment::Ssml(tag) => tag.markup(),
+                segment => format!("{:?}", segment),
+            })
+            .collect::<Vec<String>>();
+        assert_eq!(segments, vec!["oh", "<emphasis>", "Bleep", "</emphasis>", "Bleep", "no"]);
+    }
+}
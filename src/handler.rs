@@ -2,22 +2,41 @@
 use std::sync::Arc;
 
 use serenity::async_trait;
-use sqlx::query;
-use crate::config::{Server, self, User};
+use crate::config::{Server, self, User, Dictionary, Filter, SpamSettings, LanguageVoices, AudioSettings, Presets, VoicePolicy, Profile, Consent, Access, Target, PermissionSettings, Prefixes, Scope, Settings};
 use serenity::http::CacheHttp;
 use serenity::model::channel::Message;
 use serenity::prelude::*;
 use serenity::model::prelude::Ready;
-use serenity::{framework::standard::macros::{command, group}, client::EventHandler};
-use serenity::framework::standard::{StandardFramework, CommandResult};
+use serenity::{framework::standard::macros::{command, group, help, hook}, client::EventHandler};
+use crate::permissions::{self, DJ_CHECK, SETTINGS_CHECK};
+use serenity::framework::standard::{StandardFramework, CommandResult, CommandGroup, Args, Delimiter, HelpOptions, help_commands};
 use lazy_static::lazy_static;
 use tokio::join;
 use log::{debug, info, warn, error};
-use crate::tts::TTS;
+use crate::tts::{TTS, self};
+use crate::{pipeline, slash};
+use crate::filter::{Policy, BUILTIN_WORDS};
+use crate::spam::{self, Limit, RateLimits};
+use crate::language::{self, LANGUAGES};
+use crate::audio::{self, Effect, Effects};
+use crate::voices::{self, Voice};
+use crate::prefix::{self, Ignore, DEFAULT_PREFIX};
+use crate::storage::Storage;
+use crate::settings::{self as registry, Level, Setting};
+use crate::backup::{self, Backup};
+use crate::pipeline::Segment;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::mention::Mention;
+use std::time::Duration;
+use std::collections::HashSet;
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::futures::StreamExt;
+use serenity::model::interactions::{Interaction, InteractionResponseType};
+use serenity::model::interactions::message_component::ButtonStyle;
 pub struct Database;
 
 impl TypeMapKey for Database {
-    type Value = Arc<RwLock<sqlx::SqlitePool>>;
+    type Value = Arc<RwLock<Arc<dyn Storage>>>;
 }
 
 
@@ -30,80 +49,208 @@ use serenity::model::{channel, guild};
 #[async_trait]
 impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, ready: Ready) {
-        let database = {
-            let data = ctx.data.read().await;
-            data.get::<Database>().unwrap().clone().read().await.clone()
-        };
-
-
         for server in ready.guilds {
-            let serverid = server.id.0 as i64;
-            sqlx::query!("INSERT OR IGNORE INTO servers (id) VALUES (?)", serverid).execute(&database).await.unwrap();
+            if let Err(e) = Server::register(&ctx, server.id.0 as i64).await {
+                error!("Could not add server {}: {}", server.id, e);
+            }
         }
+        slash::register(&ctx).await;
         println!("{} is connected!", ready.user.name);
     }
 
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        slash::handle(&ctx, interaction).await;
+    }
+
     async fn message(&self, ctx: Context, msg: Message) {
-        let database = {
-            let data = ctx.data.read().await;
-            data.get::<Database>().unwrap().clone().read().await.clone()
-        };
-        let guild = msg.guild(&ctx.cache).unwrap();
-        
-        let db = config::Server::from_db(&ctx, &msg).await;
-        let manager = songbird::get(&ctx).await
-                        .expect("Could not get songbird manager");
-        // Check the channel in which the message was sent
-        let server_id = msg.guild_id.unwrap().0 as i64;
-        let channel_id = msg.channel_id.0 as i64;
-        // check if the message is in the server's text channel
-        let channel_id_query = sqlx::query!("SELECT text_channel FROM servers WHERE id = ?", server_id).fetch_one(&database).await.unwrap();
-        // check if the message is in the server's voice channel
-        if channel_id_query.text_channel.is_some() {
-            // Match channel_id to the text channel
-            if channel_id_query.text_channel.unwrap() == channel_id {
-                // if bot
-                if msg.author.bot {
-                    return;
-                // else if command
-                } else if msg.content.starts_with("tts!") {
-                    return;
-                } else {
-                    println!("{}: {}", msg.author.name, msg.content);
-                    let database = User::from_db(&ctx, &msg).await;
-                    let tts = TTS::request(&database.voice, &msg.content, Some(&msg)).await;
-                    let tts_file = if tts.is_err() {
-                        msg.reply(&ctx, format!("Could not print message {:?}", tts.err())).await.unwrap();
-                        return
-                    } else {
-                        tts.unwrap().download().await.unwrap()
-                    };
-                    // TODO: Put the message in the queue
-                    if let Some(handler_lock) = manager.get(guild.id) {
-                        let mut handler = handler_lock.lock().await;
-                        let source = match songbird::ffmpeg(&tts_file).await {
-                            Ok(source) => source,
-                            Err(e) => {
-                                println!("Error Starting Source: {}", e);
-
-                                return;
-                            }
-                        };
-                        handler.play_source(source);
-                        // Then delete the file
-                        //std::fs::remove_file(&tts_file).unwrap();
+        // DMs are only used for commands
+        if msg.guild_id.is_none() {
+            return;
+        }
+        if let Err(e) = read_message(&ctx, &msg).await {
+            error!("Could not read message {} in {}: {}", msg.id, msg.guild_id.unwrap(), e);
+        }
+    }
+}
 
-                    }
-                }
+/// Read a message aloud, if it was sent in the server's text channel and nothing stops it from being read
+async fn read_message(ctx: &Context, msg: &Message) -> anyhow::Result<()> {
+    let server = Server::from_db(ctx, msg).await?;
+    let server_id = server.id;
+    // Check the channel in which the message was sent
+    if server.text_channel != Some(msg.channel_id.0 as i64) {
+        return Ok(());
+    }
+    // Loaded once for everything below that depends on a setting
+    let settings = Settings::from_db(ctx, msg).await?;
+    // Skip bots, and commands for this bot or another
+    if msg.author.bot || Prefixes::from_settings(&settings).await?.ignores(&msg.content) {
+        return Ok(());
+    }
+    let spam = SpamSettings::from_settings(&settings).await?;
+    if let Some(reason) = speech_denied(ctx, msg.into(), &member_roles(msg), &spam).await? {
+        debug!("Not reading {} in {}: {}", msg.author.name, server_id, reason);
+        return Ok(());
+    }
+    println!("{}: {}", msg.author.name, msg.content);
+    let text = read_mentions(ctx, msg).await?;
+    let text = if spam.collapse { spam::collapse(&text) } else { text };
+    let text = spam::truncate(&text, spam.max_length as usize);
+
+    let user = User::from_settings(&settings).await?;
+    let policy = VoicePolicy::from_settings(&settings).await?;
+    let (mut voice, effects) = Presets::from_db(ctx, msg).await?.resolve(&user.voice, &user.effects, &policy.default_voice);
+    // Switch to a voice that speaks the message's language, if one is mapped and detection wasn't turned off
+    let detected = if settings.get(&registry::DETECT_LANGUAGE) { language::detect(&text) } else { None };
+    if let Some(language) = detected {
+        if language != tts::voice_language(&voice) {
+            if let Some(mapped) = LanguageVoices::from_db(ctx, msg).await?.resolve(language) {
+                voice = mapped.to_string();
             }
         }
     }
+    // The server may have disabled the voice since it was picked
+    let voice = match policy.enforce(&voice) {
+        Some(voice) => voice,
+        None => {
+            debug!("No voice is allowed in {}", server_id);
+            return Ok(());
+        }
+    };
+    let dictionary = Dictionary::from_db(ctx, msg).await?;
+    let filter = Filter::from_db(ctx, msg).await?;
+    // Filter the text before it is sent anywhere
+    let segments = match pipeline::prepare(&text, &voice, &dictionary.entries, &filter, &policy) {
+        Some(segments) if !segments.is_empty() => segments,
+        _ => return Ok(()),
+    };
+    let audio_settings = AudioSettings::from_settings(&settings)?;
+    let tts_file = match pipeline::synthesize(&voice, &segments, &effects, audio_settings.loudness, msg.into()).await {
+        Ok(file) => file,
+        Err(e) => {
+            msg.reply(ctx, format!("Could not print message: {}", e)).await?;
+            return Ok(());
+        }
+    };
+    // TODO: Put the message in the queue
+    play(ctx, msg.guild_id.unwrap(), &tts_file, audio_settings.volume).await;
+    // Then delete the file
+    //std::fs::remove_file(&tts_file).unwrap();
+    Ok(())
+}
+
+/// Why the bot won't speak for a user with these roles, if anything stops it: they opted out, are blocked or
+/// don't have the TTS role, or went over the rate limit. Passing the check counts towards the rate limit.
+async fn speech_denied(ctx: &Context, scope: Scope, roles: &[i64], spam: &SpamSettings) -> sqlx::Result<Option<&'static str>> {
+    // Nothing leaves the process for users who don't want their messages read
+    if !Consent::from_db(ctx, scope).await?.allows_reading() {
+        return Ok(Some("You opted out of having your messages read, run `tts!optin` first."));
+    }
+    if !Access::from_db(ctx, scope).await?.allows(scope.user_id.0 as i64, roles) {
+        return Ok(Some("You can't use text to speech in this server."));
+    }
+    let within_limits = {
+        let data = ctx.data.read().await;
+        let limiter = data.get::<RateLimits>().unwrap().clone();
+        let mut limiter = limiter.lock().await;
+        limiter.check(spam.server_id, scope.user_id.0 as i64, spam.user_limit, spam.server_limit)
+    };
+    if !within_limits {
+        return Ok(Some("Slow down, try again in a few seconds."));
+    }
+    Ok(None)
+}
+
+/// The ids of the roles of a message's author
+fn member_roles(msg: &Message) -> Vec<i64> {
+    msg.member.as_ref()
+        .map(|member| member.roles.iter().map(|role| role.0 as i64).collect())
+        .unwrap_or_default()
+}
+
+/// Replace user mentions with what the users want to be called, their alias or their name
+async fn read_mentions(ctx: &Context, msg: &Message) -> sqlx::Result<String> {
+    let ids = msg.mentions.iter().map(|user| user.id.0 as i64).collect::<Vec<i64>>();
+    let aliases = Profile::aliases(ctx, &ids).await?;
+    let mut text = msg.content.clone();
+    for user in &msg.mentions {
+        let name = aliases.get(&(user.id.0 as i64)).unwrap_or(&user.name);
+        text = text.replace(&format!("<@{}>", user.id), name).replace(&format!("<@!{}>", user.id), name);
+    }
+    Ok(text)
+}
+
+/// Play an audio file in the server's voice session. Returns false if the bot isn't in one.
+async fn play(ctx: &Context, guild_id: GuildId, file: &str, volume: f32) -> bool {
+    let manager = songbird::get(ctx).await
+                    .expect("Could not get songbird manager");
+    let handler_lock = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock,
+        None => return false,
+    };
+    let mut handler = handler_lock.lock().await;
+    let source = match songbird::ffmpeg(file).await {
+        Ok(source) => source,
+        Err(e) => {
+            println!("Error Starting Source: {}", e);
+            return true;
+        }
+    };
+    let track = handler.play_source(source);
+    if let Err(e) = track.set_volume(volume) {
+        warn!("Could not set volume: {}", e);
+    }
+    true
 }
 
 
 
+/// The channel's, server's or every server's command prefix, see `settings::PREFIX`
+#[hook]
+pub async fn dynamic_prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    match Settings::from_db(ctx, msg).await {
+        Ok(settings) => Some(settings.get(&registry::PREFIX)),
+        Err(e) => {
+            warn!("Could not get the prefix in {}: {}", msg.channel_id, e);
+            Some(DEFAULT_PREFIX.to_string())
+        }
+    }
+}
+
+/// Log commands that failed, and tell the user if it was the database's fault
+#[hook]
+pub async fn after(ctx: &Context, msg: &Message, command_name: &str, result: CommandResult) {
+    if let Err(e) = result {
+        warn!("{} failed: {}", command_name, e);
+        if e.downcast_ref::<sqlx::Error>().is_some() {
+            if let Err(e) = msg.reply(ctx, "Something went wrong, try again later.").await {
+                warn!("Could not reply to {}: {}", command_name, e);
+            }
+        }
+    }
+}
+
+/// Lists every command, or shows how to use one, from the commands' descriptions and usages
+#[help]
+#[individual_command_tip = "Pass a command's name to see how to use it, e.g. `help setvoice` or `help filter policy`."]
+#[command_not_found_text = "There is no `{}` command."]
+#[max_levenshtein_distance(3)]
+#[lacking_permissions = "Hide"]
+#[lacking_conditions = "Strike"]
+async fn help(
+    ctx: &Context,
+    msg: &Message,
+    args: Args,
+    help_options: &'static HelpOptions,
+    groups: &[&'static CommandGroup],
+    owners: HashSet<UserId>,
+) -> CommandResult {
+    help_commands::with_embeds(ctx, msg, args, help_options, groups, owners).await?;
+    Ok(())
+}
+
 #[group]
-#[commands(join, setvoice, leave)]
+#[commands(settings, join, setvoice, voicepicker, alias, optout, optin, optinonly, block, unblock, ttsrole, djrole, voices, preview, preset, defaultvoice, restrict, leave, prefix, ignore, set, reset, export, import, dict, filter, spam, langvoice, voiceopts, loudness, volume)]
 struct General;
 
 
@@ -112,89 +259,1909 @@ struct General;
 
 
 #[command]
-#[description("Join a voice channel")]
-#[usage("<channel>")]
+#[description("Join your voice channel and read this channel")]
+#[usage("")]
+#[only_in(guilds)]
+#[checks(Dj)]
 async fn join(ctx: &Context, msg: &Message) -> CommandResult {
-    let user = msg.author.clone();
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let server_id = guild.id.0 as i64;
+    msg.reply(&ctx, join_voice(ctx, msg.into()).await?).await?;
+    Ok(())
+}
 
+/// Join the user's voice channel and read the channel the command was sent in. Returns the reply,
+/// shared by the prefix and slash commands
+pub async fn join_voice(ctx: &Context, scope: Scope) -> sqlx::Result<String> {
+    let guild = match scope.guild_id.and_then(|id| id.to_guild_cached(&ctx.cache)) {
+        Some(guild) => guild,
+        None => return Ok("This command only works in servers.".to_string()),
+    };
+    let channel = match guild.voice_states.get(&scope.user_id).and_then(|state| state.channel_id) {
+        Some(channel) => channel,
+        None => return Ok("You are not in a voice channel! Please join a channel.".to_string()),
+    };
+    // Join the vc
+    let manager = songbird::get(ctx).await.unwrap();
+    let _handler = manager.join(guild.id, channel).await;
 
-    // Get the handler's database connection
-    /* let database = {
-        let data = ctx.data.read().await;
-        data.get::<Database>().unwrap().clone().read().await.clone()
-    }; */
-
-    let voice_state = guild.voice_states.get(&user.id);
-    if voice_state.is_some() {
-        // Join the voice channel
-        let channel = voice_state.unwrap().channel_id.unwrap();
-        // Join the vc
-        let manager = songbird::get(ctx).await.unwrap();
-        let _handler = manager.join(guild.id, channel).await;
-
-        // Update the database with the new voice channel and text channel
-        let channel_id = msg.channel_id.0 as i64;
-        let voice_channel = channel.0 as i64;
-        // Set the database
-        //sqlx::query!("UPDATE servers SET voice_channel = ? WHERE id = ?", voice_channel, server_id).execute(&database).await.unwrap();
-        //sqlx::query!("UPDATE servers SET text_channel = ? WHERE id = ?", channel_id, server_id).execute(&database).await.unwrap();
-        // Reply
-        msg.reply(&ctx, format!("Joining Channel <#{}>",&channel)).await?;
-        let db = config::Server::from_db(ctx, &msg).await
-            .update_text_channel(channel_id)
-            .await
-            .update_voice_channel(voice_channel)
-            .await;
-    } else {
-        msg.reply(&ctx, "You are not in a voice channel! Please join a channel.").await?;
-    }
-    Ok(())
+    // Update the database with the new voice channel and text channel
+    config::Server::from_db(ctx, scope).await?
+        .update_text_channel(scope.channel_id.0 as i64)
+        .await?
+        .update_voice_channel(channel.0 as i64)
+        .await?;
+    Ok(format!("Joining Channel <#{}>", channel))
 }
 
 #[command]
-#[description("Leave a voice channel")]
+#[description("Leave the voice channel")]
 #[usage("")]
+#[only_in(guilds)]
+#[checks(Dj)]
 async fn leave(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+    msg.reply(&ctx, leave_voice(ctx, msg.into()).await?).await?;
+    Ok(())
+}
 
+/// Leave the server's voice channel. Returns the reply
+pub async fn leave_voice(ctx: &Context, scope: Scope) -> sqlx::Result<String> {
+    let guild_id = match scope.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok("This command only works in servers.".to_string()),
+    };
     let manager = songbird::get(ctx).await
         .expect("Songbird Voice client placed in at initialisation.").clone();
-    let has_handler = manager.get(guild_id).is_some();
+    if manager.get(guild_id).is_none() {
+        return Ok("Currently not in a voice channel!".to_string());
+    }
+    if let Err(e) = manager.remove(guild_id).await {
+        return Ok(format!("Error: {}", e));
+    }
+    // Clear the database entry
+    Server::from_db(ctx, scope).await?
+        .update_voice_channel(0)
+        .await?;
+    Ok("Left voice channel.".to_string())
+}
+
+/// Whether a command changes the user's global profile rather than their settings in the server:
+/// always in DMs, and in servers with a `--global` argument, which is removed from the returned arguments
+fn take_global(msg: &Message, args: Args) -> (bool, Args) {
+    let mut global = msg.guild_id.is_none();
+    let rest = args.raw()
+        .filter(|arg| if *arg == "--global" { global = true; false } else { true })
+        .collect::<Vec<&str>>()
+        .join(" ");
+    (global, Args::new(&rest, &[Delimiter::Single(' ')]))
+}
+
+#[command]
+#[description("Set the TTS voice, either a voice id or one of the server's presets. `default` goes back to your global or the server's default voice. \
+With `--global` or in DMs, sets the voice used in every server where you haven't set one")]
+#[usage("<voice|preset|default> [--global]")]
+async fn setvoice(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, args) = take_global(msg, args);
+    // Get the argument, SAPI voice names have spaces in them
+    let voice = args.rest().trim();
+    if voice.is_empty() {
+        msg.reply(&ctx, "Usage: `tts!setvoice <voice> [--global]`. Run `tts!voices` to see every voice.").await?;
+        return Ok(());
+    }
+    msg.reply(&ctx, set_voice(ctx, msg.into(), voice, global).await?).await?;
+    Ok(())
+}
+
+/// Set the user's voice in the server or their global profile. Returns the reply
+pub async fn set_voice(ctx: &Context, scope: Scope, voice: &str, global: bool) -> sqlx::Result<String> {
+    if global {
+        let profile = Profile::from_db(ctx, scope).await?;
+        if voice.eq_ignore_ascii_case("default") {
+            profile.update_voice(None).await?;
+            return Ok("Removed your global voice, servers will use their default voice.".to_string());
+        }
+        // Presets belong to a server, so only catalog voices can be global
+        let voice = match voices::find(voice) {
+            Some(voice) => voice,
+            None => return Ok(format!("Unknown voice `{}`. Run `tts!voices` to see every voice.", voice)),
+        };
+        profile.update_voice(Some(voice.id)).await?;
+        return Ok(format!("Set your global voice to `{}` ({}). It is used in every server where you haven't set a voice.", voice.id, voice.name));
+    }
+
+    let policy = VoicePolicy::from_db(ctx, scope).await?;
+    if voice.eq_ignore_ascii_case("default") {
+        let user = User::from_db(ctx, scope).await?.reset_voice().await?;
+        return Ok(format!("You will now use `{}` in this server", user.voice));
+    }
+    // Presets take priority, they can't clash with voice ids since those always have a provider prefix
+    let presets = Presets::from_db(ctx, scope).await?;
+    let (voice, id, description) = if let Some(preset) = presets.get(voice) {
+        (preset.name.clone(), preset.voice.clone(), format!("{}, {}", preset.voice, preset.effects))
+    } else if let Some(voice) = voices::find(voice) {
+        (voice.id.to_string(), voice.id.to_string(), voice.name.to_string())
+    } else {
+        return Ok(format!("Unknown voice `{}`. Run `tts!voices` to see every voice, or `tts!preset` for this server's presets.", voice));
+    };
+    if !policy.allows(&id) {
+        return Ok(format!("`{}` is not allowed in this server. Run `tts!restrict` to see which voices are.", id));
+    }
+
+    User::from_db(ctx, scope).await?
+        .update_voice(&voice)
+        .await?;
+    Ok(format!("Set voice to `{}` ({})", voice, description))
+}
+
+#[command]
+#[description("Hear what a voice sounds like before setting it")]
+#[usage("<voice|preset> [text]")]
+#[min_args(1)]
+#[only_in(guilds)]
+async fn preview(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let reply = preview_voice(ctx, msg.into(), &member_roles(msg), args.rest().trim()).await?;
+    send_preview(ctx, msg, reply).await
+}
+
+/// Preview a voice or preset from `<voice|preset> [text]`, shared by the prefix and slash commands.
+/// Returns the reply, and the audio to send with it if the bot isn't in a voice channel
+pub async fn preview_voice(ctx: &Context, scope: Scope, roles: &[i64], query: &str) -> sqlx::Result<(String, Option<String>)> {
+    let first = query.split_whitespace().next().unwrap_or_default();
+    let presets = Presets::from_db(ctx, scope).await?;
+    let (name, voice, effects, text) = if let Some(preset) = presets.get(first) {
+        let voice = match voices::find(&preset.voice) {
+            Some(voice) => voice,
+            None => return Ok((format!("Preset `{}` uses unknown voice `{}`", preset.name, preset.voice), None)),
+        };
+        (format!("`{}` ({}, {})", preset.name, voice.id, preset.effects), voice, preset.effects, query[first.len()..].trim())
+    } else {
+        match voices::find_prefix(query) {
+            Some((voice, text)) => (format!("`{}` ({})", voice.id, voice.name), voice, Effects::default(), text),
+            None => return Ok((format!("Unknown voice `{}`. Run `tts!voices` to see every voice.", query), None)),
+        }
+    };
+    let text = if text.is_empty() { None } else { Some(text) };
+    render_preview(ctx, scope, roles, voice, &effects, text, &name).await
+}
+
+/// Read some text with a voice, or the voice's sample text without any, in the voice channel if the bot is in one.
+/// `name` describes the voice in the reply, which comes with the audio if it wasn't played
+async fn render_preview(ctx: &Context, scope: Scope, roles: &[i64], voice: &Voice, effects: &Effects, text: Option<&str>, name: &str) -> sqlx::Result<(String, Option<String>)> {
+    let settings = Settings::from_db(ctx, scope).await?;
+    let policy = VoicePolicy::from_settings(&settings).await?;
+    if !policy.allows(voice.id) {
+        return Ok((format!("`{}` is not allowed in this server. Run `tts!restrict` to see which voices are.", voice.id), None));
+    }
+    // Previews go through the same checks as messages that are read
+    let spam = SpamSettings::from_settings(&settings).await?;
+    if let Some(reason) = speech_denied(ctx, scope, roles, &spam).await? {
+        return Ok((reason.to_string(), None));
+    }
+    let text = match text {
+        Some(text) => spam::truncate(text, spam.max_length as usize),
+        None => voices::sample_text(voice.language).to_string(),
+    };
+
+    let dictionary = Dictionary::from_db(ctx, scope).await?;
+    let filter = Filter::from_db(ctx, scope).await?;
+    let segments = match pipeline::prepare(&text, voice.id, &dictionary.entries, &filter, &policy) {
+        Some(segments) if segments.iter().any(|s| matches!(s, Segment::Speech(_))) => segments,
+        _ => return Ok(("That text can't be read in this server.".to_string(), None)),
+    };
+    let audio_settings = AudioSettings::from_settings(&settings)?;
+    let file = match pipeline::synthesize(voice.id, &segments, effects, audio_settings.loudness, scope).await {
+        Ok(file) => file,
+        Err(e) => return Ok((format!("Could not preview {}: {}", name, e), None)),
+    };
+
+    let guild_id = scope.guild_id.expect("previews only run in servers");
+    if play(ctx, guild_id, &file, audio_settings.volume).await {
+        Ok((format!("Playing a preview of {}", name), None))
+    } else {
+        // Not in a voice channel, send the audio instead
+        Ok((format!("Preview of {}", name), Some(file)))
+    }
+}
+
+/// Reply to a message with a preview, attaching its audio if it has any
+async fn send_preview(ctx: &Context, msg: &Message, (reply, file): (String, Option<String>)) -> CommandResult {
+    match file {
+        Some(file) => {
+            msg.channel_id.send_message(&ctx.http, |m| {
+                m.reference_message(msg)
+                    .content(reply)
+                    .add_file(file.as_str())
+            }).await?;
+        }
+        None => {
+            msg.reply(&ctx, reply).await?;
+        }
+    }
+    Ok(())
+}
+
+/// How long the voice picker keeps working
+const PICKER_TIMEOUT: Duration = Duration::from_secs(300);
+/// Discord allows at most 25 options per select menu, and 5 rows per message
+const MAX_SELECT_OPTIONS: usize = 25;
+const MAX_VOICE_MENUS: usize = 2;
+
+/// What has been picked so far in `tts!voicepicker`
+#[derive(Default)]
+struct Picker {
+    provider: Option<String>,
+    language: Option<String>,
+    voice: Option<&'static Voice>,
+}
+
+impl Picker {
+    fn content(&self) -> String {
+        match (&self.provider, &self.language, self.voice) {
+            (None, _, _) => "Pick a provider.".to_string(),
+            (Some(_), None, _) => "Pick a language.".to_string(),
+            (Some(_), Some(_), None) => "Pick a voice.".to_string(),
+            (_, _, Some(voice)) => format!("Picked `{}` ({}, {}). Preview it, or use it as your voice.", voice.id, voice.name, voice.gender),
+        }
+    }
+}
+
+fn picker_components<'a>(components: &'a mut CreateComponents, picker: &Picker, allowed: &[&'static Voice]) -> &'a mut CreateComponents {
+    let providers = voices::PROVIDERS.iter()
+        .filter(|provider| allowed.iter().any(|voice| voice.provider() == **provider))
+        .collect::<Vec<_>>();
+    components.create_action_row(|row| {
+        row.create_select_menu(|menu| {
+            menu.custom_id("provider").placeholder("Provider").options(|options| {
+                for provider in &providers {
+                    options.create_option(|o| {
+                        o.label(provider).value(provider).default_selection(picker.provider.as_deref() == Some(**provider))
+                    });
+                }
+                options
+            })
+        })
+    });
+
+    if let Some(provider) = &picker.provider {
+        let mut languages = allowed.iter()
+            .filter(|voice| voice.provider() == provider.as_str())
+            .map(|voice| voice.language)
+            .collect::<Vec<&str>>();
+        languages.sort_unstable();
+        languages.dedup();
+        components.create_action_row(|row| {
+            row.create_select_menu(|menu| {
+                menu.custom_id("language").placeholder("Language").options(|options| {
+                    for language in languages.iter().take(MAX_SELECT_OPTIONS) {
+                        options.create_option(|o| {
+                            o.label(language).value(language).default_selection(picker.language.as_deref() == Some(*language))
+                        });
+                    }
+                    options
+                })
+            })
+        });
+
+        if let Some(language) = &picker.language {
+            let matching = allowed.iter()
+                .filter(|voice| voice.provider() == provider.as_str() && voice.language == language.as_str())
+                .collect::<Vec<_>>();
+            for (i, chunk) in matching.chunks(MAX_SELECT_OPTIONS).take(MAX_VOICE_MENUS).enumerate() {
+                components.create_action_row(|row| {
+                    row.create_select_menu(|menu| {
+                        menu.custom_id(format!("voice-{}", i)).placeholder("Voice").options(|options| {
+                            for voice in chunk {
+                                options.create_option(|o| {
+                                    o.label(voice.name)
+                                        .value(voice.id)
+                                        .description(format!("{}, {}", voice.id, voice.gender))
+                                        .default_selection(picker.voice.map_or(false, |v| v.id == voice.id))
+                                });
+                            }
+                            options
+                        })
+                    })
+                });
+            }
+        }
+    }
+
+    components.create_action_row(|row| {
+        row.create_button(|b| b.custom_id("preview").label("Preview").style(ButtonStyle::Secondary).disabled(picker.voice.is_none()))
+            .create_button(|b| b.custom_id("use").label("Use this voice").style(ButtonStyle::Primary).disabled(picker.voice.is_none()))
+    })
+}
+
+#[command]
+#[description("Pick your voice from menus instead of typing its id")]
+#[usage("")]
+#[only_in(guilds)]
+async fn voicepicker(ctx: &Context, msg: &Message) -> CommandResult {
+    let policy = VoicePolicy::from_db(ctx, msg).await?;
+    let allowed = voices::CATALOG.iter().filter(|voice| policy.allows(voice.id)).collect::<Vec<&Voice>>();
+    if allowed.is_empty() {
+        msg.reply(&ctx, "No voice is allowed in this server.").await?;
+        return Ok(());
+    }
+
+    let mut picker = Picker::default();
+    let mut message = msg.channel_id.send_message(&ctx.http, |m| {
+        m.reference_message(msg)
+            .content(picker.content())
+            .components(|c| picker_components(c, &picker, &allowed))
+    }).await?;
+
+    let mut interactions = message.await_component_interactions(&ctx)
+        .author_id(msg.author.id)
+        .timeout(PICKER_TIMEOUT)
+        .build();
+    while let Some(interaction) = interactions.next().await {
+        let value = interaction.data.values.first().cloned();
+        match interaction.data.custom_id.as_str() {
+            "provider" => {
+                picker = Picker { provider: value, ..Picker::default() };
+            }
+            "language" => {
+                picker.language = value;
+                picker.voice = None;
+            }
+            id if id.starts_with("voice-") => {
+                picker.voice = value.as_deref().and_then(voices::find);
+            }
+            "preview" => {
+                interaction.create_interaction_response(&ctx.http, |r| r.kind(InteractionResponseType::DeferredUpdateMessage)).await?;
+                if let Some(voice) = picker.voice {
+                    let name = format!("`{}` ({})", voice.id, voice.name);
+                    let reply = render_preview(ctx, msg.into(), &member_roles(msg), voice, &Effects::default(), None, &name).await?;
+                    send_preview(ctx, msg, reply).await?;
+                }
+                continue;
+            }
+            "use" => {
+                let voice = match picker.voice {
+                    Some(voice) => voice,
+                    None => continue,
+                };
+                User::from_db(ctx, msg).await?.update_voice(voice.id).await?;
+                interaction.create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|d| {
+                            d.content(format!("Set voice to `{}` ({})", voice.id, voice.name)).components(|c| c)
+                        })
+                }).await?;
+                return Ok(());
+            }
+            _ => continue,
+        }
+        interaction.create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| {
+                    d.content(picker.content()).components(|c| picker_components(c, &picker, &allowed))
+                })
+        }).await?;
+    }
+
+    // Timed out, remove the menus
+    message.edit(&ctx.http, |m| m.components(|c| c)).await?;
+    Ok(())
+}
+
+/// Voices listed on each page of `tts!voices`
+const VOICES_PER_PAGE: usize = 15;
+/// How long the page buttons keep working
+const PAGINATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[command]
+#[description("List the available voices, optionally for one provider and/or language")]
+#[usage("[provider] [language]")]
+#[max_args(2)]
+async fn voices(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut provider = None;
+    let mut language = None;
+    for arg in args.raw() {
+        if voices::PROVIDERS.contains(&arg.to_lowercase().as_str()) {
+            provider = Some(arg.to_lowercase());
+        } else {
+            language = Some(arg.to_lowercase());
+        }
+    }
+
+    match voice_lines(provider.as_deref(), language.as_deref()) {
+        Ok(lines) => paginate(ctx, msg, "Voices", &lines, VOICES_PER_PAGE).await,
+        Err(e) => {
+            msg.reply(&ctx, e).await?;
+            Ok(())
+        }
+    }
+}
+
+/// A line describing each voice of a provider and/or language, shared by the prefix and slash commands
+pub fn voice_lines(provider: Option<&str>, language: Option<&str>) -> Result<Vec<String>, String> {
+    let matches = voices::search(provider, language);
+    if matches.is_empty() {
+        return Err(format!("No voices found. Providers are `{}`, languages are codes like `en` or `ja`.", voices::PROVIDERS.join("`, `")));
+    }
+    Ok(matches.iter()
+        .map(|voice| format!("`{}` {} ({}, {})", voice.id, voice.name, voice.language, voice.gender))
+        .collect())
+}
+
+fn page_embed<'a>(embed: &'a mut CreateEmbed, title: &str, pages: &[String], page: usize) -> &'a mut CreateEmbed {
+    embed.title(title)
+        .description(&pages[page])
+        .footer(|f| f.text(format!("Page {} of {}", page + 1, pages.len())))
+}
+
+fn page_buttons(components: &mut CreateComponents, page: usize, pages: usize) -> &mut CreateComponents {
+    if pages <= 1 {
+        return components;
+    }
+    components.create_action_row(|row| {
+        row.create_button(|b| b.custom_id("previous").label("Previous").style(ButtonStyle::Secondary).disabled(page == 0))
+            .create_button(|b| b.custom_id("next").label("Next").style(ButtonStyle::Secondary).disabled(page + 1 == pages))
+    })
+}
+
+/// Reply with an embed split into pages, with buttons the author can use to flip through them
+async fn paginate(ctx: &Context, msg: &Message, title: &str, lines: &[String], per_page: usize) -> CommandResult {
+    let pages = lines.chunks(per_page).map(|chunk| chunk.join("\n")).collect::<Vec<String>>();
+    let mut page = 0;
+    let mut message = msg.channel_id.send_message(&ctx.http, |m| {
+        m.reference_message(msg)
+            .embed(|e| page_embed(e, title, &pages, page))
+            .components(|c| page_buttons(c, page, pages.len()))
+    }).await?;
+    if pages.len() <= 1 {
+        return Ok(());
+    }
+
+    let mut interactions = message.await_component_interactions(&ctx)
+        .author_id(msg.author.id)
+        .timeout(PAGINATION_TIMEOUT)
+        .build();
+    while let Some(interaction) = interactions.next().await {
+        match interaction.data.custom_id.as_str() {
+            "previous" => page = page.saturating_sub(1),
+            "next" => page = (page + 1).min(pages.len() - 1),
+            _ => continue,
+        }
+        interaction.create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| {
+                    d.embed(|e| page_embed(e, title, &pages, page))
+                        .components(|c| page_buttons(c, page, pages.len()))
+                })
+        }).await?;
+    }
+
+    // Timed out, remove the buttons
+    message.edit(&ctx.http, |m| m.components(|c| c)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Show the server's settings, and your own voice and options")]
+#[usage("")]
+#[only_in(guilds)]
+async fn settings(ctx: &Context, msg: &Message) -> CommandResult {
+    let fields = settings_fields(ctx, msg.into()).await?;
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.reference_message(msg).embed(|e| e.title("Settings").fields(fields))
+    }).await?;
+    Ok(())
+}
+
+/// The server's settings, and the user's own voice and options, as (title, value, inline) fields.
+/// Shared by the prefix and slash commands
+pub async fn settings_fields(ctx: &Context, scope: Scope) -> sqlx::Result<Vec<(&'static str, String, bool)>> {
+    let settings = Settings::from_db(ctx, scope).await?;
+    let server = Server::from_db(ctx, scope).await?;
+    let prefixes = Prefixes::from_settings(&settings).await?;
+    let policy = VoicePolicy::from_settings(&settings).await?;
+    let presets = Presets::from_db(ctx, scope).await?;
+    let dictionary = Dictionary::from_db(ctx, scope).await?;
+    let filter = Filter::from_db(ctx, scope).await?;
+    let spam = SpamSettings::from_settings(&settings).await?;
+    let audio_settings = AudioSettings::from_settings(&settings)?;
+    let access = Access::from_db(ctx, scope).await?;
+    let user = User::from_settings(&settings).await?;
+    let consent = Consent::from_db(ctx, scope).await?;
+
+    let channel = |id: Option<i64>| id.map_or_else(|| "not set".to_string(), |id| format!("<#{}>", id));
+    let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+    let role = |id: i64| {
+        scope.guild_id.and_then(|guild_id| ctx.cache.guild(guild_id))
+            .and_then(|guild| guild.roles.get(&RoleId(id as u64)).map(|role| format!("@{}", role.name)))
+            .unwrap_or_else(|| id.to_string())
+    };
 
-    if has_handler {
-        if let Err(e) = manager.remove(guild_id).await {
-            msg.reply(&ctx, format!("Error: {}", e)).await?;
+    let channels = format!(
+        "Text: {}\nVoice: {}\nPrefix: `{}`\nIgnored prefixes: {}",
+        channel(server.text_channel),
+        channel(server.voice_channel),
+        prefixes.prefix,
+        prefixes.ignored.len(),
+    );
+    let restrictions = match (policy.entries.len(), policy.allowlist) {
+        (0, _) => "none".to_string(),
+        (n, true) => format!("{} allowed", n),
+        (n, false) => format!("{} disallowed", n),
+    };
+    let voices = format!(
+        "Default voice: `{}`\nRestrictions: {}\nPresets: {}",
+        policy.default_voice,
+        restrictions,
+        presets.presets.len(),
+    );
+    let filters = format!(
+        "Content filter: {} ({})\nDictionary entries: {}\nUser limit: {} messages every {} seconds\nServer limit: {} messages every {} seconds\nMax length: {} characters",
+        on_off(filter.enabled),
+        filter.policy,
+        dictionary.entries.len(),
+        spam.user_limit.messages,
+        spam.user_limit.seconds,
+        spam.server_limit.messages,
+        spam.server_limit.seconds,
+        spam.max_length,
+    );
+    let reading = format!(
+        "Opt-in only: {}\nTTS role: {}\nBlocked: {}\nLoudness: {}\nVolume: {}%",
+        on_off(consent.optin_only),
+        access.tts_role.map_or_else(|| "off".to_string(), role),
+        access.blocked.len(),
+        audio_settings.loudness.map_or_else(|| "off".to_string(), |loudness| format!("{} LUFS", loudness)),
+        (audio_settings.volume * 100.0).round(),
+    );
+    let yours = format!(
+        "Voice: `{}`\nOptions: {}\nRead: {}",
+        user.voice,
+        user.effects,
+        if consent.allows_reading() { "yes" } else { "no" },
+    );
+
+    Ok(vec![
+        ("Channels", channels, true),
+        ("Voices", voices, true),
+        ("Filters", filters, false),
+        ("Reading", reading, true),
+        ("Your settings", yours, true),
+    ])
+}
+
+/// The level asked for with `--user`, `--channel`, `--server` or `--global`, which is removed from the returned arguments
+fn take_level(args: Args) -> Result<(Option<Level>, Args), String> {
+    let mut level = None;
+    let mut rest = Vec::new();
+    for arg in args.raw() {
+        match arg.strip_prefix("--").map(str::parse::<Level>) {
+            Some(Ok(flag)) if level.is_none() => level = Some(flag),
+            Some(Ok(_)) => return Err("Pick only one of `--user`, `--channel`, `--server` and `--global`".to_string()),
+            Some(Err(e)) => return Err(e),
+            None => rest.push(arg),
         }
-        // Clear the database entry
-        msg.reply(&ctx, "Left voice channel.").await?;
-        let database = Server::from_db(&ctx, &msg).await
-            .update_voice_channel(0)
-            .await;
+    }
+    Ok((level, Args::new(&rest.join(" "), &[Delimiter::Single(' ')])))
+}
+
+/// The level a setting is changed at without a flag: the server's in servers and your own in DMs,
+/// or the most specific one the setting has
+fn default_level(msg: &Message, setting: &dyn Setting) -> Level {
+    let levels = setting.levels();
+    let preferred = if msg.guild_id.is_some() { Level::Server } else { Level::User };
+    if levels.contains(&preferred) {
+        preferred
+    } else if msg.guild_id.is_some() {
+        levels[0]
     } else {
-        msg.reply(&ctx, "Currently not in a voice channel!").await?;
+        Level::Global
+    }
+}
+
+fn describe_level(level: Level) -> &'static str {
+    match level {
+        Level::User => "you",
+        Level::Channel => "this channel",
+        Level::Server => "this server",
+        Level::Global => "every server",
+    }
+}
+
+/// Check the level and the author's permissions for `set` and `reset`, replying if they can't change the setting there
+async fn check_level(ctx: &Context, msg: &Message, setting: &dyn Setting, level: Level) -> Result<bool, serenity::framework::standard::CommandError> {
+    if !setting.levels().contains(&level) {
+        let levels = setting.levels().iter().map(|level| describe_level(*level)).collect::<Vec<&str>>();
+        msg.reply(&ctx, format!("`{}` can't be set for {}, only for {}", setting.name(), describe_level(level), levels.join(", "))).await?;
+        return Ok(false);
+    }
+    if let Err(e) = permissions::can_set(ctx, msg, level).await {
+        msg.reply(&ctx, e).await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Check the author's permissions before a command changes a server setting, replying if they can't.
+/// Commands check this themselves so that anyone can still see the current value
+async fn can_change(ctx: &Context, msg: &Message) -> Result<bool, serenity::framework::standard::CommandError> {
+    if let Err(e) = permissions::can_set(ctx, msg, Level::Server).await {
+        msg.reply(&ctx, e).await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+#[command]
+#[description("Change a setting for yourself, this channel, this server or every server. The most specific level that is set wins. \
+Without a value, shows the setting at every level, and without arguments lists every setting")]
+#[usage("[--user|--channel|--server|--global] [<setting> [value]]")]
+async fn set(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (level, mut args) = match take_level(args) {
+        Ok(taken) => taken,
+        Err(e) => {
+            msg.reply(&ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let settings = Settings::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        let list = registry::REGISTRY.iter()
+            .map(|setting| format!("`{}` = `{}`: {}", setting.name(), settings.effective(*setting), setting.description()))
+            .collect::<Vec<String>>()
+            .join("\n");
+        msg.reply(&ctx, format!("{}\nRun `tts!set <setting>` to see where a setting is set.", list)).await?;
+        return Ok(());
+    }
+
+    let name = args.single::<String>()?;
+    let setting = match registry::find(&name) {
+        Some(setting) => setting,
+        None => {
+            msg.reply(&ctx, format!("Unknown setting `{}`, run `tts!set` to list them", name)).await?;
+            return Ok(());
+        }
+    };
+    let value = args.rest().trim();
+    if value.is_empty() {
+        let levels = setting.levels().iter()
+            .map(|level| format!("{}: {}", level, settings.value(*level, setting.name()).map_or_else(|| "not set".to_string(), |value| format!("`{}`", value))))
+            .collect::<Vec<String>>()
+            .join("\n");
+        msg.reply(&ctx, format!("`{}` is `{}`. {}\n{}\ndefault: `{}`", setting.name(), settings.effective(setting), setting.description(), levels, setting.default())).await?;
+        return Ok(());
+    }
+
+    let level = level.unwrap_or_else(|| default_level(msg, setting));
+    if !check_level(ctx, msg, setting, level).await? {
+        return Ok(());
+    }
+    let value = match setting.normalize(value) {
+        Ok(value) => value,
+        Err(e) => {
+            msg.reply(&ctx, e).await?;
+            return Ok(());
+        }
+    };
+    settings.set(level, setting.name(), &value).await?;
+    msg.reply(&ctx, format!("Set `{}` to `{}` for {}", setting.name(), value, describe_level(level))).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Remove a setting changed with `tts!set`, so the next level's value or the default is used")]
+#[usage("[--user|--channel|--server|--global] <setting>")]
+async fn reset(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (level, args) = match take_level(args) {
+        Ok(taken) => taken,
+        Err(e) => {
+            msg.reply(&ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let setting = match registry::find(args.rest().trim()) {
+        Some(setting) => setting,
+        None => {
+            msg.reply(&ctx, "Usage: `tts!reset [--user|--channel|--server|--global] <setting>`. Run `tts!set` to list the settings.").await?;
+            return Ok(());
+        }
+    };
+    let level = level.unwrap_or_else(|| default_level(msg, setting));
+    if !check_level(ctx, msg, setting, level).await? {
+        return Ok(());
+    }
+    let settings = Settings::from_db(ctx, msg).await?.reset(level, setting.name()).await?;
+    msg.reply(&ctx, format!("Reset `{}` for {}, it is `{}` now", setting.name(), describe_level(level), settings.effective(setting))).await?;
+    Ok(())
+}
+
+/// Largest file `tts!import` downloads
+const MAX_IMPORT_SIZE: u64 = 1 << 20;
+/// Problems with an imported file listed in the reply, so it stays under Discord's message length
+const MAX_IMPORT_ERRORS: usize = 15;
+
+#[command]
+#[description("Download the server's settings as a JSON file: the channels the bot joins and reads, the dictionary, presets, filter, \
+settings changed with `tts!set` for the whole server and everyone's voices. Settings changed for a single channel aren't included. \
+Use `tts!import` to restore them, here or in another server")]
+#[only_in(guilds)]
+#[checks(Settings)]
+async fn export(ctx: &Context, msg: &Message) -> CommandResult {
+    let backup = Backup::from_db(ctx, msg).await?;
+    let data = serde_json::to_vec_pretty(&backup)?;
+    let filename = format!("urusai-{}.json", msg.guild_id.unwrap());
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.reference_message(msg)
+            .content("Here are the server's settings. Attach the file to `tts!import` to restore them.")
+            .add_file((data.as_slice(), filename.as_str()))
+    }).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Restore settings from a file made with `tts!export`, attached to the message. \
+Replaces the dictionary, presets, filter and server settings, and sets the voices of the users in the file")]
+#[only_in(guilds)]
+#[checks(Settings)]
+async fn import(ctx: &Context, msg: &Message) -> CommandResult {
+    let attachment = match msg.attachments.first() {
+        Some(attachment) => attachment,
+        None => {
+            msg.reply(&ctx, "Attach a file made with `tts!export` to the message.").await?;
+            return Ok(());
+        }
+    };
+    if attachment.size > MAX_IMPORT_SIZE {
+        msg.reply(&ctx, "That file is too big to be from `tts!export`.").await?;
+        return Ok(());
+    }
+    let data = attachment.download().await?;
+    let mut file = match backup::parse(&data) {
+        Ok(file) => file,
+        Err(errors) => {
+            let mut reply = format!("Nothing was imported, the file has {} problem{}:", errors.len(), if errors.len() == 1 { "" } else { "s" });
+            for error in errors.iter().take(MAX_IMPORT_ERRORS) {
+                reply.push_str(&format!("\n- {}", error));
+            }
+            if errors.len() > MAX_IMPORT_ERRORS {
+                reply.push_str(&format!("\n...and {} more", errors.len() - MAX_IMPORT_ERRORS));
+            }
+            msg.reply(&ctx, reply).await?;
+            return Ok(());
+        }
+    };
+
+    // When cloning another server's settings, its channels don't exist here
+    let mut channel_note = "";
+    let channels = msg.guild_id.unwrap().channels(&ctx.http).await?;
+    for channel in [&mut file.voice_channel, &mut file.text_channel] {
+        if channel.as_deref().and_then(backup::parse_id).is_some_and(|channel_id| !channels.contains_key(&ChannelId(channel_id as u64))) {
+            *channel = None;
+            channel_note = " The channels are from another server, so they weren't changed.";
+        }
+    }
+    file.restore(ctx, msg).await?;
+    let settings = file.settings.as_ref().map_or(0, |settings| settings.len());
+    msg.reply(&ctx, format!("Imported {} dictionary entries, {} presets, {} filtered words, {} settings and the voices of {} users.{}",
+        file.dictionary.len(), file.presets.len(), file.filter.words.len(), settings, file.users.len(), channel_note)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Change the bot's command prefix in this server")]
+#[usage("[prefix]")]
+#[max_args(1)]
+#[only_in(guilds)]
+async fn prefix(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let prefixes = Prefixes::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        msg.reply(&ctx, format!("The prefix is `{}`", prefixes.prefix)).await?;
+        return Ok(());
     }
 
+    if !can_change(ctx, msg).await? {
+        return Ok(());
+    }
+    let new_prefix = args.single::<String>()?;
+    if let Err(e) = prefix::validate(&new_prefix) {
+        msg.reply(&ctx, e).await?;
+        return Ok(());
+    }
+    prefixes.update_prefix(&new_prefix).await?;
+    msg.reply(&ctx, format!("Set the prefix to `{}`, e.g. `{}join`", new_prefix, new_prefix)).await?;
     Ok(())
 }
 
 #[command]
-#[description("Set the TTS voice")]
-#[usage("<voice>")]
-async fn setvoice(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
+#[description("Manage other bots' command prefixes, which are never read. Wrap an entry in slashes to use a regex, e.g. `/^[-$]\\w+/`")]
+#[usage("[add <prefix|/regex/> | remove <prefix|/regex/> | list]")]
+#[sub_commands(ignore_add, ignore_remove, ignore_list)]
+#[only_in(guilds)]
+async fn ignore(ctx: &Context, msg: &Message) -> CommandResult {
+    ignore_list(ctx, msg, Args::new("", &[])).await
+}
 
-    // Get the argument
-    let args = msg.content.split_whitespace().collect::<Vec<&str>>();
-    let voice = args[1];
+#[command("add")]
+#[description("Don't read messages starting with a prefix, or matching a `/regex/`")]
+#[usage("<prefix|/regex/>")]
+#[min_args(1)]
+#[checks(Settings)]
+async fn ignore_add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let ignore = match args.rest().parse::<Ignore>() {
+        Ok(ignore) => ignore,
+        Err(e) => {
+            msg.reply(&ctx, e).await?;
+            return Ok(());
+        }
+    };
+    Prefixes::from_db(ctx, msg).await?.add_ignored(ignore.clone()).await?;
+    msg.reply(&ctx, format!("Messages matching `{}` won't be read anymore", ignore)).await?;
+    Ok(())
+}
 
-    let _database = User::from_db(&ctx, &msg).await
-        .update_voice(voice)
-        .await;
+#[command("remove")]
+#[description("Read messages starting with a prefix, or matching a `/regex/`, again")]
+#[usage("<prefix|/regex/>")]
+#[min_args(1)]
+#[checks(Settings)]
+async fn ignore_remove(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let ignore = match args.rest().parse::<Ignore>() {
+        Ok(ignore) => ignore,
+        Err(e) => {
+            msg.reply(&ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let prefixes = Prefixes::from_db(ctx, msg).await?;
+    if !prefixes.ignored.contains(&ignore) {
+        msg.reply(&ctx, format!("`{}` is not ignored", ignore)).await?;
+        return Ok(());
+    }
+    prefixes.remove_ignored(&ignore).await?;
+    msg.reply(&ctx, format!("Messages matching `{}` will be read again", ignore)).await?;
+    Ok(())
+}
+
+#[command("list")]
+#[description("List the ignored prefixes")]
+async fn ignore_list(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let prefixes = Prefixes::from_db(ctx, msg).await?;
+    let ignored = if prefixes.ignored.is_empty() {
+        "none".to_string()
+    } else {
+        prefixes.ignored.iter().map(|ignore| format!("`{}`", ignore)).collect::<Vec<String>>().join(", ")
+    };
+    msg.reply(&ctx, format!("Commands with the bot's prefix `{}` are never read.\nIgnored: {}", prefixes.prefix, ignored)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Manage the server's pronunciation dictionary")]
+#[usage("[add <word> <replacement> | remove <word> | list]")]
+#[sub_commands(dict_add, dict_remove, dict_list)]
+#[only_in(guilds)]
+async fn dict(ctx: &Context, msg: &Message) -> CommandResult {
+    dict_list(ctx, msg, Args::new("", &[])).await
+}
+
+#[command("add")]
+#[description("Read a word as something else. Adding a word as itself disables the built-in expansion, e.g. `add lol lol`")]
+#[usage("<word> <replacement>")]
+#[min_args(2)]
+#[checks(Settings)]
+async fn dict_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let word = args.single::<String>()?;
+    let replacement = args.rest().trim();
+
+    let _database = Dictionary::from_db(ctx, msg).await?
+        .add(&word, replacement)
+        .await?;
+
+    msg.reply(&ctx, format!("`{}` will now be read as `{}`", word, replacement)).await?;
+    Ok(())
+}
+
+#[command("remove")]
+#[description("Remove a word from the dictionary")]
+#[usage("<word>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn dict_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let word = args.single::<String>()?;
+
+    let dictionary = Dictionary::from_db(ctx, msg).await?;
+    if !dictionary.entries.contains_key(&word.to_lowercase()) {
+        msg.reply(&ctx, format!("`{}` is not in the dictionary", word)).await?;
+        return Ok(());
+    }
+    dictionary.remove(&word).await?;
 
-    msg.reply(&ctx, format!("Set voice to `{}`", voice)).await?;
+    msg.reply(&ctx, format!("Removed `{}` from the dictionary", word)).await?;
     Ok(())
 }
 
+#[command("list")]
+#[description("List the server's dictionary entries")]
+async fn dict_list(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let dictionary = Dictionary::from_db(ctx, msg).await?;
+    if dictionary.entries.is_empty() {
+        msg.reply(&ctx, "The dictionary is empty.").await?;
+        return Ok(());
+    }
+
+    let mut entries = dictionary.entries.iter().collect::<Vec<_>>();
+    entries.sort();
+    let list = entries.iter()
+        .map(|(word, replacement)| format!("`{}` → `{}`", word, replacement))
+        .collect::<Vec<String>>()
+        .join("\n");
+    msg.reply(&ctx, list).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Manage the content filter for spoken messages")]
+#[usage("[on | off | policy <skip|bleep|replace> [word] | builtin <on|off> | add <word> | remove <word>]")]
+#[sub_commands(filter_on, filter_off, filter_policy, filter_builtin, filter_add, filter_remove)]
+#[only_in(guilds)]
+async fn filter(ctx: &Context, msg: &Message) -> CommandResult {
+    let filter = Filter::from_db(ctx, msg).await?;
+    let policy = match filter.policy {
+        Policy::Replace => format!("replace with `{}`", filter.replacement),
+        policy => policy.to_string(),
+    };
+    let words = if filter.words.is_empty() {
+        "none".to_string()
+    } else {
+        format!("`{}`", filter.words.join("`, `"))
+    };
+    msg.reply(&ctx, format!(
+        "Filter is **{}**, policy: {}\nBuilt-in list ({} words): {}\nCustom words: {}",
+        if filter.enabled { "on" } else { "off" },
+        policy,
+        BUILTIN_WORDS.len(),
+        if filter.builtin { "on" } else { "off" },
+        words,
+    )).await?;
+    Ok(())
+}
+
+#[command("on")]
+#[description("Enable the content filter")]
+#[checks(Settings)]
+async fn filter_on(ctx: &Context, msg: &Message) -> CommandResult {
+    Filter::from_db(ctx, msg).await?.update_enabled(true).await?;
+    msg.reply(&ctx, "Content filter enabled.").await?;
+    Ok(())
+}
+
+#[command("off")]
+#[description("Disable the content filter")]
+#[checks(Settings)]
+async fn filter_off(ctx: &Context, msg: &Message) -> CommandResult {
+    Filter::from_db(ctx, msg).await?.update_enabled(false).await?;
+    msg.reply(&ctx, "Content filter disabled.").await?;
+    Ok(())
+}
+
+#[command("policy")]
+#[description("Set what happens to filtered words: skip the message, bleep the word or replace it with another word")]
+#[usage("<skip|bleep|replace> [replacement]")]
+#[min_args(1)]
+#[checks(Settings)]
+async fn filter_policy(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let policy = match args.single::<String>()?.parse::<Policy>() {
+        Ok(policy) => policy,
+        Err(e) => {
+            msg.reply(&ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let replacement = args.remains().map(|r| r.trim());
+
+    let filter = Filter::from_db(ctx, msg).await?
+        .update_policy(policy, replacement)
+        .await?;
+
+    msg.reply(&ctx, match policy {
+        Policy::Replace => format!("Filtered words will be replaced with `{}`", filter.replacement),
+        policy => format!("Filter policy set to `{}`", policy),
+    }).await?;
+    Ok(())
+}
+
+#[command("builtin")]
+#[description("Toggle the built-in word list")]
+#[usage("<on|off>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn filter_builtin(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let enabled = match args.single::<String>()?.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(&ctx, "Expected `on` or `off`").await?;
+            return Ok(());
+        }
+    };
+    Filter::from_db(ctx, msg).await?.update_builtin(enabled).await?;
+    msg.reply(&ctx, format!("Built-in word list {}.", if enabled { "enabled" } else { "disabled" })).await?;
+    Ok(())
+}
+
+#[command("add")]
+#[description("Add a word to the filter")]
+#[usage("<word>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn filter_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let word = args.single::<String>()?;
+    Filter::from_db(ctx, msg).await?.add_word(&word).await?;
+    msg.reply(&ctx, format!("Added `{}` to the filter", word)).await?;
+    Ok(())
+}
+
+#[command("remove")]
+#[description("Remove a word from the filter")]
+#[usage("<word>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn filter_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let word = args.single::<String>()?;
+    Filter::from_db(ctx, msg).await?.remove_word(&word).await?;
+    msg.reply(&ctx, format!("Removed `{}` from the filter", word)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Manage anti-spam limits. Setting a limit to 0 disables it")]
+#[usage("[user <messages> <seconds> | server <messages> <seconds> | maxlength <characters> | collapse <on|off>]")]
+#[sub_commands(spam_user, spam_server, spam_maxlength, spam_collapse)]
+#[only_in(guilds)]
+async fn spam(ctx: &Context, msg: &Message) -> CommandResult {
+    let spam = SpamSettings::from_db(ctx, msg).await?;
+    msg.reply(&ctx, format!(
+        "Per-user limit: {} messages every {} seconds\nServer limit: {} messages every {} seconds\nMax length: {} characters\nCollapse repetition: {}",
+        spam.user_limit.messages,
+        spam.user_limit.seconds,
+        spam.server_limit.messages,
+        spam.server_limit.seconds,
+        spam.max_length,
+        if spam.collapse { "on" } else { "off" },
+    )).await?;
+    Ok(())
+}
+
+#[command("user")]
+#[description("Set how many messages a single user can have read in a time window")]
+#[usage("<messages> <seconds>")]
+#[num_args(2)]
+#[checks(Settings)]
+async fn spam_user(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let limit = Limit { messages: args.single()?, seconds: args.single()? };
+    SpamSettings::from_db(ctx, msg).await?.update_user_limit(limit).await?;
+    msg.reply(&ctx, format!("Users can now have {} messages read every {} seconds", limit.messages, limit.seconds)).await?;
+    Ok(())
+}
+
+#[command("server")]
+#[description("Set how many messages can be read in the whole server in a time window")]
+#[usage("<messages> <seconds>")]
+#[num_args(2)]
+#[checks(Settings)]
+async fn spam_server(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let limit = Limit { messages: args.single()?, seconds: args.single()? };
+    SpamSettings::from_db(ctx, msg).await?.update_server_limit(limit).await?;
+    msg.reply(&ctx, format!("The server can now have {} messages read every {} seconds", limit.messages, limit.seconds)).await?;
+    Ok(())
+}
+
+#[command("maxlength")]
+#[description("Set the maximum number of characters read from a message")]
+#[usage("<characters>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn spam_maxlength(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let max_length = args.single::<u32>()?;
+    SpamSettings::from_db(ctx, msg).await?.update_max_length(max_length).await?;
+    msg.reply(&ctx, format!("Max spoken length set to {} characters", max_length)).await?;
+    Ok(())
+}
+
+#[command("collapse")]
+#[description("Toggle collapsing of repeated characters and words")]
+#[usage("<on|off>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn spam_collapse(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let collapse = match args.single::<String>()?.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(&ctx, "Expected `on` or `off`").await?;
+            return Ok(());
+        }
+    };
+    SpamSettings::from_db(ctx, msg).await?.update_collapse(collapse).await?;
+    msg.reply(&ctx, format!("Repetition collapsing {}.", if collapse { "enabled" } else { "disabled" })).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Manage the voices used when a message is in another language")]
+#[usage("[set <language> <voice> | unset <language> | serverset <language> <voice> | serverunset <language>]")]
+#[sub_commands(langvoice_set, langvoice_unset, langvoice_serverset, langvoice_serverunset)]
+#[only_in(guilds)]
+async fn langvoice(ctx: &Context, msg: &Message) -> CommandResult {
+    let voices = LanguageVoices::from_db(ctx, msg).await?;
+    let list = |map: &std::collections::HashMap<String, String>| {
+        if map.is_empty() {
+            return "none".to_string();
+        }
+        let mut entries = map.iter().map(|(language, voice)| format!("`{}` → `{}`", language, voice)).collect::<Vec<String>>();
+        entries.sort();
+        entries.join(", ")
+    };
+    msg.reply(&ctx, format!("Your voices: {}\nServer voices: {}", list(&voices.user), list(&voices.server))).await?;
+    Ok(())
+}
+
+/// Check the language argument of the langvoice commands, replying if it isn't supported
+async fn parse_language(ctx: &Context, msg: &Message, args: &mut Args) -> Result<Option<String>, serenity::framework::standard::CommandError> {
+    let language = args.single::<String>()?.to_lowercase();
+    if !LANGUAGES.contains(&language.as_str()) {
+        msg.reply(&ctx, format!("Unknown language `{}`, expected one of `{}`", language, LANGUAGES.join("`, `"))).await?;
+        return Ok(None);
+    }
+    Ok(Some(language))
+}
+
+/// Look up the voice argument in the catalog, replying if it doesn't exist or isn't allowed in the server
+async fn parse_voice(ctx: &Context, msg: &Message, args: &mut Args) -> Result<Option<&'static str>, serenity::framework::standard::CommandError> {
+    let voice = args.rest().trim();
+    match voices::find(voice) {
+        Some(voice) if VoicePolicy::from_db(ctx, msg).await?.allows(voice.id) => Ok(Some(voice.id)),
+        Some(voice) => {
+            msg.reply(&ctx, format!("`{}` is not allowed in this server. Run `tts!restrict` to see which voices are.", voice.id)).await?;
+            Ok(None)
+        }
+        None => {
+            msg.reply(&ctx, format!("Unknown voice `{}`. Run `tts!voices` to see every voice.", voice)).await?;
+            Ok(None)
+        }
+    }
+}
+
+#[command("set")]
+#[description("Use a voice for your messages in a language, e.g. `set ja tiktok-jp_001`")]
+#[usage("<language> <voice>")]
+#[min_args(2)]
+async fn langvoice_set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let language = match parse_language(ctx, msg, &mut args).await? {
+        Some(language) => language,
+        None => return Ok(()),
+    };
+    let voice = match parse_voice(ctx, msg, &mut args).await? {
+        Some(voice) => voice,
+        None => return Ok(()),
+    };
+    LanguageVoices::from_db(ctx, msg).await?.update_user(&language, Some(voice)).await?;
+    msg.reply(&ctx, format!("Your `{}` messages will be read with `{}`", language, voice)).await?;
+    Ok(())
+}
+
+#[command("unset")]
+#[description("Stop switching voices for your messages in a language")]
+#[usage("<language>")]
+#[num_args(1)]
+async fn langvoice_unset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let language = match parse_language(ctx, msg, &mut args).await? {
+        Some(language) => language,
+        None => return Ok(()),
+    };
+    LanguageVoices::from_db(ctx, msg).await?.update_user(&language, None).await?;
+    msg.reply(&ctx, format!("Removed your voice for `{}`", language)).await?;
+    Ok(())
+}
+
+#[command("serverset")]
+#[description("Use a voice for everyone's messages in a language, unless they set their own")]
+#[usage("<language> <voice>")]
+#[min_args(2)]
+#[checks(Settings)]
+async fn langvoice_serverset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let language = match parse_language(ctx, msg, &mut args).await? {
+        Some(language) => language,
+        None => return Ok(()),
+    };
+    let voice = match parse_voice(ctx, msg, &mut args).await? {
+        Some(voice) => voice,
+        None => return Ok(()),
+    };
+    LanguageVoices::from_db(ctx, msg).await?.update_server(&language, Some(voice)).await?;
+    msg.reply(&ctx, format!("`{}` messages will be read with `{}`", language, voice)).await?;
+    Ok(())
+}
+
+#[command("serverunset")]
+#[description("Remove the server-wide voice for a language")]
+#[usage("<language>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn langvoice_serverunset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let language = match parse_language(ctx, msg, &mut args).await? {
+        Some(language) => language,
+        None => return Ok(()),
+    };
+    LanguageVoices::from_db(ctx, msg).await?.update_server(&language, None).await?;
+    msg.reply(&ctx, format!("Removed the server voice for `{}`", language)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Change how your voice sounds. With `--global` or in DMs, changes the options used in every server where you haven't set any")]
+#[usage("[rate <0.5-2.0> | pitch <-12-12> | gain <-20-10> | effect <none|echo|radio|robot> | reset] [--global]")]
+#[sub_commands(voiceopts_rate, voiceopts_pitch, voiceopts_gain, voiceopts_effect, voiceopts_reset)]
+async fn voiceopts(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, _) = take_global(msg, args);
+    msg.reply(&ctx, show_effects(ctx, msg.into(), global).await?).await?;
+    Ok(())
+}
+
+/// The user's voice options in the server or their global profile
+pub async fn show_effects(ctx: &Context, scope: Scope, global: bool) -> sqlx::Result<String> {
+    let effects = if global {
+        Profile::from_db(ctx, scope).await?.effects.unwrap_or_default()
+    } else {
+        User::from_db(ctx, scope).await?.effects
+    };
+    Ok(format!(
+        "Rate: {}x\nPitch: {:+} semitones\nGain: {:+} dB\nEffect: {}",
+        effects.rate, effects.pitch, effects.gain, effects.effect,
+    ))
+}
+
+/// Parse a number for the voiceopts commands, replying if it is out of range
+async fn parse_option(ctx: &Context, msg: &Message, args: &mut Args, range: (f32, f32)) -> Result<Option<f32>, serenity::framework::standard::CommandError> {
+    let value = args.single::<f32>()?;
+    // "nan" and "inf" parse as f32, and NaN compares false with everything
+    if !value.is_finite() || !(range.0..=range.1).contains(&value) {
+        msg.reply(&ctx, format!("Expected a value between {} and {}", range.0, range.1)).await?;
+        return Ok(None);
+    }
+    Ok(Some(value))
+}
+
+/// Change one of the user's voice options, in their global profile or in this server
+pub async fn change_effects(ctx: &Context, scope: Scope, global: bool, change: impl FnOnce(&mut Effects)) -> sqlx::Result<()> {
+    if global {
+        let profile = Profile::from_db(ctx, scope).await?;
+        let mut effects = profile.effects.unwrap_or_default();
+        change(&mut effects);
+        profile.update_effects(Some(effects)).await?;
+    } else {
+        let user = User::from_db(ctx, scope).await?;
+        let mut effects = user.effects;
+        change(&mut effects);
+        user.update_effects(effects).await?;
+    }
+    Ok(())
+}
+
+/// Suffix for the voiceopts replies
+pub fn scope_suffix(global: bool) -> &'static str {
+    if global { " everywhere" } else { " in this server" }
+}
+
+#[command("rate")]
+#[description("Set your speaking rate, 1.0 is normal speed")]
+#[usage("<0.5-2.0> [--global]")]
+#[min_args(1)]
+#[max_args(2)]
+async fn voiceopts_rate(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, mut args) = take_global(msg, args);
+    let rate = match parse_option(ctx, msg, &mut args, Effects::RATE_RANGE).await? {
+        Some(rate) => rate,
+        None => return Ok(()),
+    };
+    change_effects(ctx, msg.into(), global, |effects| effects.rate = rate).await?;
+    msg.reply(&ctx, format!("Set rate to {}x{}", rate, scope_suffix(global))).await?;
+    Ok(())
+}
+
+#[command("pitch")]
+#[description("Shift your voice's pitch by a number of semitones")]
+#[usage("<-12-12> [--global]")]
+#[min_args(1)]
+#[max_args(2)]
+async fn voiceopts_pitch(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, mut args) = take_global(msg, args);
+    let pitch = match parse_option(ctx, msg, &mut args, Effects::PITCH_RANGE).await? {
+        Some(pitch) => pitch,
+        None => return Ok(()),
+    };
+    change_effects(ctx, msg.into(), global, |effects| effects.pitch = pitch).await?;
+    msg.reply(&ctx, format!("Set pitch to {:+} semitones{}", pitch, scope_suffix(global))).await?;
+    Ok(())
+}
+
+#[command("gain")]
+#[description("Make your voice louder or quieter, in dB")]
+#[usage("<-20-10> [--global]")]
+#[min_args(1)]
+#[max_args(2)]
+async fn voiceopts_gain(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, mut args) = take_global(msg, args);
+    let gain = match parse_option(ctx, msg, &mut args, Effects::GAIN_RANGE).await? {
+        Some(gain) => gain,
+        None => return Ok(()),
+    };
+    change_effects(ctx, msg.into(), global, |effects| effects.gain = gain).await?;
+    msg.reply(&ctx, format!("Set gain to {:+} dB{}", gain, scope_suffix(global))).await?;
+    Ok(())
+}
+
+#[command("effect")]
+#[description("Add an effect to your voice")]
+#[usage("<none|echo|radio|robot> [--global]")]
+#[min_args(1)]
+#[max_args(2)]
+async fn voiceopts_effect(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, mut args) = take_global(msg, args);
+    let effect = match args.single::<String>()?.parse::<Effect>() {
+        Ok(effect) => effect,
+        Err(e) => {
+            msg.reply(&ctx, e).await?;
+            return Ok(());
+        }
+    };
+    change_effects(ctx, msg.into(), global, |effects| effects.effect = effect).await?;
+    msg.reply(&ctx, format!("Set effect to `{}`{}", effect, scope_suffix(global))).await?;
+    Ok(())
+}
+
+#[command("reset")]
+#[description("Reset your voice options in this server to your global ones, or your global ones to the defaults")]
+#[usage("[--global]")]
+async fn voiceopts_reset(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, _) = take_global(msg, args);
+    msg.reply(&ctx, reset_effects(ctx, msg.into(), global).await?).await?;
+    Ok(())
+}
+
+/// Reset the user's voice options in the server or their global profile. Returns the reply
+pub async fn reset_effects(ctx: &Context, scope: Scope, global: bool) -> sqlx::Result<String> {
+    Ok(if global {
+        Profile::from_db(ctx, scope).await?.update_effects(None).await?;
+        "Reset your global voice options.".to_string()
+    } else {
+        User::from_db(ctx, scope).await?.reset_effects().await?;
+        "Reset your voice options in this server.".to_string()
+    })
+}
+
+#[command]
+#[description("Set what the bot calls you when someone mentions you, in every server")]
+#[usage("[name | off]")]
+async fn alias(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    msg.reply(&ctx, set_alias(ctx, msg.into(), args.rest().trim()).await?).await?;
+    Ok(())
+}
+
+/// Longest alias a user can set, the same as Discord's nickname limit
+const MAX_ALIAS_LENGTH: usize = 32;
+
+/// Show the user's alias if `alias` is empty, remove it if it's `off`, or set it. Returns the reply
+pub async fn set_alias(ctx: &Context, scope: Scope, alias: &str) -> sqlx::Result<String> {
+    let profile = Profile::from_db(ctx, scope).await?;
+    if alias.is_empty() {
+        return Ok(match &profile.alias {
+            Some(alias) => format!("Mentions of you are read as `{}`", alias),
+            None => "Mentions of you are read as your username.".to_string(),
+        });
+    }
+    if alias.eq_ignore_ascii_case("off") {
+        profile.update_alias(None).await?;
+        return Ok("Mentions of you will be read as your username.".to_string());
+    }
+    if alias.chars().count() > MAX_ALIAS_LENGTH {
+        return Ok(format!("Aliases can be at most {} characters long", MAX_ALIAS_LENGTH));
+    }
+    profile.update_alias(Some(alias)).await?;
+    Ok(format!("Mentions of you will be read as `{}`", alias))
+}
+
+#[command]
+#[description("Stop the bot from reading your messages in this server, or everywhere with `--global` or in DMs")]
+#[usage("[--global]")]
+async fn optout(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, _) = take_global(msg, args);
+    msg.reply(&ctx, opt_out(ctx, msg.into(), global).await?).await?;
+    Ok(())
+}
+
+/// Stop reading the user in the server, or everywhere. Returns the reply
+pub async fn opt_out(ctx: &Context, scope: Scope, global: bool) -> sqlx::Result<String> {
+    let consent = Consent::from_db(ctx, scope).await?;
+    Ok(if global {
+        consent.update_global_optout(true).await?;
+        "Your messages won't be read in any server.".to_string()
+    } else {
+        consent.update_server(false).await?;
+        "Your messages won't be read in this server.".to_string()
+    })
+}
+
+#[command]
+#[description("Let the bot read your messages in this server, or undo a global opt-out with `--global` or in DMs")]
+#[usage("[--global]")]
+async fn optin(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (global, _) = take_global(msg, args);
+    msg.reply(&ctx, opt_in(ctx, msg.into(), global).await?).await?;
+    Ok(())
+}
+
+/// Read the user in the server, or undo a global opt-out. Returns the reply
+pub async fn opt_in(ctx: &Context, scope: Scope, global: bool) -> sqlx::Result<String> {
+    let consent = Consent::from_db(ctx, scope).await?;
+    Ok(if global {
+        consent.update_global_optout(false).await?;
+        "Removed your global opt-out. Servers where you opted out still won't read your messages.".to_string()
+    } else if consent.global_optout {
+        "You opted out of every server. Run `tts!optin --global` first.".to_string()
+    } else {
+        consent.update_server(true).await?;
+        "Your messages will be read in this server.".to_string()
+    })
+}
+
+#[command]
+#[description("Only read the messages of users who ran `tts!optin`")]
+#[usage("[on|off]")]
+#[max_args(1)]
+#[only_in(guilds)]
+async fn optinonly(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let consent = Consent::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        msg.reply(&ctx, format!("Opt-in only mode is {}.", if consent.optin_only { "on" } else { "off" })).await?;
+        return Ok(());
+    }
+    if !can_change(ctx, msg).await? {
+        return Ok(());
+    }
+    let optin_only = match args.single::<String>()?.to_lowercase().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(&ctx, "Expected `on` or `off`").await?;
+            return Ok(());
+        }
+    };
+    consent.update_optin_only(optin_only).await?;
+    msg.reply(&ctx, if optin_only {
+        "Only users who ran `tts!optin` will be read now."
+    } else {
+        "Everyone who didn't opt out will be read now."
+    }).await?;
+    Ok(())
+}
+
+/// Parse a user or role mention, replying if it's neither. Also returns a name to reply with, so nobody gets pinged
+async fn parse_target(ctx: &Context, msg: &Message, args: &mut Args) -> Result<Option<(Target, String)>, serenity::framework::standard::CommandError> {
+    let argument = args.single::<String>()?;
+    match argument.parse::<Mention>() {
+        Ok(Mention::User(id)) => {
+            let name = msg.mentions.iter().find(|user| user.id == id).map_or_else(|| id.to_string(), |user| user.name.clone());
+            Ok(Some((Target::User(id.0 as i64), name)))
+        }
+        Ok(Mention::Role(id)) => {
+            let name = msg.guild(&ctx.cache)
+                .and_then(|guild| guild.roles.get(&id).map(|role| format!("@{}", role.name)))
+                .unwrap_or_else(|| id.to_string());
+            Ok(Some((Target::Role(id.0 as i64), name)))
+        }
+        _ => {
+            msg.reply(&ctx, "Expected a user or role mention").await?;
+            Ok(None)
+        }
+    }
+}
+
+#[command]
+#[description("Stop reading a user's messages, or those of everyone with a role. Without an argument, lists who is blocked")]
+#[usage("[@user|@role]")]
+#[max_args(1)]
+#[only_in(guilds)]
+async fn block(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let access = Access::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        if access.blocked.is_empty() {
+            msg.reply(&ctx, "Nobody is blocked.").await?;
+            return Ok(());
+        }
+        let guild = msg.guild(&ctx.cache);
+        let names = access.blocked.iter()
+            .map(|target| match target {
+                Target::User(id) => format!("user `{}`", id),
+                Target::Role(id) => guild.as_ref()
+                    .and_then(|guild| guild.roles.get(&RoleId(*id as u64)).map(|role| format!("role `@{}`", role.name)))
+                    .unwrap_or_else(|| format!("role `{}`", id)),
+            })
+            .collect::<Vec<String>>();
+        msg.reply(&ctx, format!("Blocked: {}", names.join(", "))).await?;
+        return Ok(());
+    }
+
+    if !can_change(ctx, msg).await? {
+        return Ok(());
+    }
+    let (target, name) = match parse_target(ctx, msg, &mut args).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    access.block(target).await?;
+    msg.reply(&ctx, format!("Messages from `{}` won't be read anymore.", name)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Read a blocked user's or role's messages again")]
+#[usage("<@user|@role>")]
+#[num_args(1)]
+#[only_in(guilds)]
+#[checks(Settings)]
+async fn unblock(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (target, name) = match parse_target(ctx, msg, &mut args).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    Access::from_db(ctx, msg).await?.unblock(target).await?;
+    msg.reply(&ctx, format!("Messages from `{}` will be read again.", name)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Only read members with a role, or everyone with `off`")]
+#[usage("[@role|off]")]
+#[max_args(1)]
+#[only_in(guilds)]
+async fn ttsrole(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let access = Access::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        let role = access.tts_role.map(|id| {
+            msg.guild(&ctx.cache)
+                .and_then(|guild| guild.roles.get(&RoleId(id as u64)).map(|role| format!("@{}", role.name)))
+                .unwrap_or_else(|| id.to_string())
+        });
+        msg.reply(&ctx, match role {
+            Some(role) => format!("Only members with `{}` are read.", role),
+            None => "Every member is read.".to_string(),
+        }).await?;
+        return Ok(());
+    }
+    if !can_change(ctx, msg).await? {
+        return Ok(());
+    }
+    if args.current().map_or(false, |arg| arg.eq_ignore_ascii_case("off")) {
+        access.update_tts_role(None).await?;
+        msg.reply(&ctx, "Every member will be read now.").await?;
+        return Ok(());
+    }
+    match parse_target(ctx, msg, &mut args).await? {
+        Some((Target::Role(role), name)) => {
+            access.update_tts_role(Some(role)).await?;
+            msg.reply(&ctx, format!("Only members with `{}` will be read now.", name)).await?;
+        }
+        Some((Target::User(_), _)) => {
+            msg.reply(&ctx, "Expected a role mention").await?;
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Let members with a role run `tts!join`, `tts!leave` and change the bot's settings, or nobody with `off`")]
+#[usage("[@role|off]")]
+#[max_args(1)]
+#[only_in(guilds)]
+async fn djrole(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let settings = PermissionSettings::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        let role = settings.dj_role.map(|id| {
+            msg.guild(&ctx.cache)
+                .and_then(|guild| guild.roles.get(&RoleId(id as u64)).map(|role| format!("@{}", role.name)))
+                .unwrap_or_else(|| id.to_string())
+        });
+        msg.reply(&ctx, match role {
+            Some(role) => format!("The DJ role is `{}`.", role),
+            None => "There is no DJ role.".to_string(),
+        }).await?;
+        return Ok(());
+    }
+    if let Err(e) = permissions::can_pick_dj_role(ctx, msg).await {
+        msg.reply(&ctx, e).await?;
+        return Ok(());
+    }
+    if args.current().map_or(false, |arg| arg.eq_ignore_ascii_case("off")) {
+        settings.update_dj_role(None).await?;
+        msg.reply(&ctx, "Removed the DJ role.").await?;
+        return Ok(());
+    }
+    match parse_target(ctx, msg, &mut args).await? {
+        Some((Target::Role(role), name)) => {
+            settings.update_dj_role(Some(role)).await?;
+            msg.reply(&ctx, format!("Members with `{}` can now control the bot.", name)).await?;
+        }
+        Some((Target::User(_), _)) => {
+            msg.reply(&ctx, "Expected a role mention").await?;
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Set the loudness every message is normalized to, in LUFS, or turn normalization off")]
+#[usage("[<-30 to -5> | off]")]
+#[max_args(1)]
+#[only_in(guilds)]
+async fn loudness(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let settings = AudioSettings::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        msg.reply(&ctx, match settings.loudness {
+            Some(loudness) => format!("Messages are normalized to {} LUFS", loudness),
+            None => "Loudness normalization is off.".to_string(),
+        }).await?;
+        return Ok(());
+    }
+
+    if !can_change(ctx, msg).await? {
+        return Ok(());
+    }
+    let argument = args.single::<String>()?;
+    let loudness = if argument == "off" {
+        None
+    } else {
+        let (min, max) = audio::LOUDNESS_RANGE;
+        match argument.parse::<f32>() {
+            Ok(loudness) if loudness >= min && loudness <= max => Some(loudness),
+            _ => {
+                msg.reply(&ctx, format!("Expected `off` or a loudness between {} and {} LUFS", min, max)).await?;
+                return Ok(());
+            }
+        }
+    };
+    settings.update_loudness(loudness).await?;
+    msg.reply(&ctx, match loudness {
+        Some(loudness) => format!("Messages will be normalized to {} LUFS", loudness),
+        None => "Loudness normalization disabled.".to_string(),
+    }).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Set the master volume of the bot in this server, in percent")]
+#[usage("[0-200]")]
+#[max_args(1)]
+#[only_in(guilds)]
+async fn volume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let settings = AudioSettings::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        msg.reply(&ctx, format!("Volume is {}%", (settings.volume * 100.0).round())).await?;
+        return Ok(());
+    }
+
+    if !can_change(ctx, msg).await? {
+        return Ok(());
+    }
+    let percent = match args.single::<u32>() {
+        Ok(percent) if percent <= 200 => percent,
+        _ => {
+            msg.reply(&ctx, "Expected a volume between 0 and 200").await?;
+            return Ok(());
+        }
+    };
+    settings.update_volume(percent as f32 / 100.0).await?;
+    msg.reply(&ctx, format!("Set volume to {}%", percent)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Manage the server's voice presets, which users can pick with `tts!setvoice <name>`")]
+#[usage("[add <name> <voice> [rate=<x>] [pitch=<x>] [gain=<x>] [effect=<x>] | remove <name>]")]
+#[sub_commands(preset_add, preset_remove)]
+#[only_in(guilds)]
+async fn preset(ctx: &Context, msg: &Message) -> CommandResult {
+    let presets = Presets::from_db(ctx, msg).await?;
+    if presets.presets.is_empty() {
+        msg.reply(&ctx, "This server has no presets.").await?;
+        return Ok(());
+    }
+    let mut lines = presets.presets.values()
+        .map(|preset| format!("`{}` → `{}`, {}", preset.name, preset.voice, preset.effects))
+        .collect::<Vec<String>>();
+    lines.sort();
+    msg.reply(&ctx, lines.join("\n")).await?;
+    Ok(())
+}
+
+#[command("add")]
+#[description("Add or replace a preset, e.g. `add narrator ttsmp3-Brian rate=0.9 pitch=-2`")]
+#[usage("<name> <voice> [rate=<0.5-2.0>] [pitch=<-12-12>] [gain=<-20-10>] [effect=<none|echo|radio|robot>]")]
+#[min_args(2)]
+#[checks(Settings)]
+async fn preset_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>()?.to_lowercase();
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        msg.reply(&ctx, "Preset names can only contain letters, numbers and underscores").await?;
+        return Ok(());
+    }
+
+    // Everything that isn't an option is the voice, SAPI voice names have spaces in them
+    let mut effects = Effects::default();
+    let mut voice = Vec::new();
+    for arg in args.rest().split_whitespace() {
+        let (key, value) = match arg.split_once('=') {
+            Some(option) => option,
+            None => {
+                voice.push(arg);
+                continue;
+            }
+        };
+        let parsed = match key {
+            "rate" => value.parse().ok().filter(|v| *v >= Effects::RATE_RANGE.0 && *v <= Effects::RATE_RANGE.1).map(|v| effects.rate = v),
+            "pitch" => value.parse().ok().filter(|v| *v >= Effects::PITCH_RANGE.0 && *v <= Effects::PITCH_RANGE.1).map(|v| effects.pitch = v),
+            "gain" => value.parse().ok().filter(|v| *v >= Effects::GAIN_RANGE.0 && *v <= Effects::GAIN_RANGE.1).map(|v| effects.gain = v),
+            "effect" => value.parse().ok().map(|v| effects.effect = v),
+            _ => None,
+        };
+        if parsed.is_none() {
+            msg.reply(&ctx, format!("Invalid option `{}`. Options are `rate=0.5-2.0`, `pitch=-12-12`, `gain=-20-10` and `effect=none|echo|radio|robot`.", arg)).await?;
+            return Ok(());
+        }
+    }
+    let voice = match voices::find(&voice.join(" ")) {
+        Some(voice) => voice,
+        None => {
+            msg.reply(&ctx, format!("Unknown voice `{}`. Run `tts!voices` to see every voice.", voice.join(" "))).await?;
+            return Ok(());
+        }
+    };
+    if !VoicePolicy::from_db(ctx, msg).await?.allows(voice.id) {
+        msg.reply(&ctx, format!("`{}` is not allowed in this server. Run `tts!restrict` to see which voices are.", voice.id)).await?;
+        return Ok(());
+    }
+
+    Presets::from_db(ctx, msg).await?.add(&name, voice.id, effects).await?;
+    msg.reply(&ctx, format!("Added preset `{}`: `{}`, {}", name, voice.id, effects)).await?;
+    Ok(())
+}
+
+#[command("remove")]
+#[description("Remove a preset. Users who picked it go back to the default voice")]
+#[usage("<name>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn preset_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>()?;
+    let presets = Presets::from_db(ctx, msg).await?;
+    if presets.get(&name).is_none() {
+        msg.reply(&ctx, format!("There is no preset called `{}`", name)).await?;
+        return Ok(());
+    }
+    presets.remove(&name).await?;
+    msg.reply(&ctx, format!("Removed preset `{}`", name)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Set the voice users get until they pick their own with `tts!setvoice`")]
+#[usage("[voice]")]
+#[only_in(guilds)]
+async fn defaultvoice(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let policy = VoicePolicy::from_db(ctx, msg).await?;
+    if args.is_empty() {
+        msg.reply(&ctx, format!("The default voice is `{}`", policy.default_voice)).await?;
+        return Ok(());
+    }
+
+    if !can_change(ctx, msg).await? {
+        return Ok(());
+    }
+    let voice = match voices::find(args.rest().trim()) {
+        Some(voice) => voice,
+        None => {
+            msg.reply(&ctx, format!("Unknown voice `{}`. Run `tts!voices` to see every voice.", args.rest().trim())).await?;
+            return Ok(());
+        }
+    };
+    if !policy.allows(voice.id) {
+        msg.reply(&ctx, format!("`{}` is not allowed in this server. Run `tts!restrict` to see which voices are.", voice.id)).await?;
+        return Ok(());
+    }
+    policy.update_default_voice(voice.id).await?;
+    msg.reply(&ctx, format!("Set the default voice to `{}` ({})", voice.id, voice.name)).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Restrict which providers and voices can be used in the server")]
+#[usage("[mode <allow|deny> | add <provider|voice> | remove <provider|voice>]")]
+#[sub_commands(restrict_mode, restrict_add, restrict_remove)]
+#[only_in(guilds)]
+async fn restrict(ctx: &Context, msg: &Message) -> CommandResult {
+    let policy = VoicePolicy::from_db(ctx, msg).await?;
+    let mut entries = policy.entries.iter().map(|entry| format!("`{}`", entry)).collect::<Vec<String>>();
+    entries.sort();
+    let reply = match (policy.allowlist, entries.is_empty()) {
+        (false, true) => "Every voice is allowed.".to_string(),
+        (false, false) => format!("Every voice is allowed except: {}", entries.join(", ")),
+        (true, true) => "No voice is allowed, so no messages are read. Use `tts!restrict add` to allow some.".to_string(),
+        (true, false) => format!("Only these are allowed: {}", entries.join(", ")),
+    };
+    msg.reply(&ctx, reply).await?;
+    Ok(())
+}
+
+#[command("mode")]
+#[description("`allow` to only allow the listed providers and voices, `deny` to allow everything but them")]
+#[usage("<allow|deny>")]
+#[num_args(1)]
+#[checks(Settings)]
+async fn restrict_mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let allowlist = match args.single::<String>()?.to_lowercase().as_str() {
+        "allow" => true,
+        "deny" => false,
+        _ => {
+            msg.reply(&ctx, "Expected `allow` or `deny`").await?;
+            return Ok(());
+        }
+    };
+    VoicePolicy::from_db(ctx, msg).await?.update_allowlist(allowlist).await?;
+    msg.reply(&ctx, if allowlist {
+        "Only the listed providers and voices are allowed now."
+    } else {
+        "The listed providers and voices are disallowed now."
+    }).await?;
+    Ok(())
+}
+
+/// Check a provider or voice id argument of the restrict commands, replying if it's neither
+async fn parse_rule(ctx: &Context, msg: &Message, args: &Args) -> Result<Option<String>, serenity::framework::standard::CommandError> {
+    let entry = args.rest().trim();
+    if let Some(provider) = voices::PROVIDERS.iter().find(|p| p.eq_ignore_ascii_case(entry)) {
+        return Ok(Some(provider.to_string()));
+    }
+    if let Some(voice) = voices::find(entry) {
+        return Ok(Some(voice.id.to_string()));
+    }
+    msg.reply(&ctx, format!("`{}` is neither a provider (`{}`) nor a voice", entry, voices::PROVIDERS.join("`, `"))).await?;
+    Ok(None)
+}
+
+#[command("add")]
+#[description("Add a provider or voice to the list, e.g. `add tiktok` or `add ttsmp3-Brian`")]
+#[usage("<provider|voice>")]
+#[min_args(1)]
+#[checks(Settings)]
+async fn restrict_add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let entry = match parse_rule(ctx, msg, &args).await? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    let policy = VoicePolicy::from_db(ctx, msg).await?.add_entry(&entry).await?;
+    msg.reply(&ctx, format!("Added `{}` to the {}", entry, if policy.allowlist { "allowlist" } else { "denylist" })).await?;
+    Ok(())
+}
+
+#[command("remove")]
+#[description("Remove a provider or voice from the list")]
+#[usage("<provider|voice>")]
+#[min_args(1)]
+#[checks(Settings)]
+async fn restrict_remove(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let entry = match parse_rule(ctx, msg, &args).await? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    let policy = VoicePolicy::from_db(ctx, msg).await?.remove_entry(&entry).await?;
+    msg.reply(&ctx, format!("Removed `{}` from the {}", entry, if policy.allowlist { "allowlist" } else { "denylist" })).await?;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+// Language detection, used to switch to a voice that can actually read the message.
+
+use whatlang::{Lang, Script};
+
+/// Languages a voice can be mapped to, as ISO 639-1 codes
+pub static LANGUAGES: &[&str] = &[
+    "en", "ja", "ko", "zh", "fr", "de", "es", "pt", "it", "id", "ru", "nl", "pl", "tr", "vi", "th", "ar",
+];
+
+fn iso_639_1(lang: Lang) -> Option<&'static str> {
+    Some(match lang {
+        Lang::Eng => "en",
+        Lang::Jpn => "ja",
+        Lang::Kor => "ko",
+        Lang::Cmn => "zh",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Ind => "id",
+        Lang::Rus => "ru",
+        Lang::Nld => "nl",
+        Lang::Pol => "pl",
+        Lang::Tur => "tr",
+        Lang::Vie => "vi",
+        Lang::Tha => "th",
+        Lang::Ara => "ar",
+        _ => return None,
+    })
+}
+
+/// Detect the language of a message as an ISO 639-1 code.
+/// Returns `None` when the guess isn't reliable, which is common for short Latin-script messages.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    // Kana and Hangul give the language away even in a single word
+    let certain = matches!(info.script(), Script::Hiragana | Script::Katakana | Script::Hangul);
+    if !certain && !info.is_reliable() {
+        return None;
+    }
+    iso_639_1(info.lang())
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("こんにちは"), Some("ja"));
+        assert_eq!(detect("안녕하세요"), Some("ko"));
+        assert_eq!(detect("This is clearly an English sentence about the weather today."), Some("en"));
+        assert_eq!(detect("ok"), None);
+    }
+}
@@ -3,6 +3,20 @@ use std::sync::Arc;
 mod handler;
 mod config;
 mod tts;
+mod normalize;
+mod filter;
+mod audio;
+mod pipeline;
+mod spam;
+mod language;
+mod markup;
+mod voices;
+mod permissions;
+mod slash;
+mod prefix;
+mod settings;
+mod storage;
+mod backup;
 use handler::Handler;
 use songbird::SerenityInit;
 
@@ -11,29 +25,27 @@ use pretty_env_logger;
 use serenity::prelude::*;
 use serenity::framework::standard::macros::{command, group};
 use serenity::framework::standard::{StandardFramework, CommandResult};
-use sqlx::Executor;
 use crate::handler::GENERAL_GROUP;
 use crate::handler::Database;
+use crate::spam::{RateLimits, RateLimiter};
 
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
     let framework = StandardFramework::new()
-        .configure(|c| c.prefix("tts!")) // set the bot's prefix to "~"
+        // Each server picks its own prefix, tts! by default
+        .configure(|c| c.dynamic_prefix(handler::dynamic_prefix).prefix(""))
+        .on_dispatch_error(permissions::dispatch_error)
+        .after(handler::after)
+        .help(&handler::HELP)
         .group(&GENERAL_GROUP);
 
-    let database = sqlx::sqlite::SqlitePoolOptions::new()
-        .connect_with(
-            sqlx::sqlite::SqliteConnectOptions::new()
-                .filename("database.db")
-                .create_if_missing(true),
-        )
+    // SQLite or PostgreSQL, depending on the URL's scheme
+    let database_url = env::var("DATABASE_URL").unwrap_or_else(|_| storage::DEFAULT_DATABASE_URL.to_string());
+    let database = storage::connect(&database_url)
         .await
         .expect("Couldn't connect to database");
-    let schema = include_str!("schema.sql");
-    let executor = database.clone();
-    executor.execute(schema).await.unwrap();
 
     pretty_env_logger::init();
 
@@ -56,6 +68,7 @@ async fn main() {
         let mut data = client.data.write().await;
 
         data.insert::<Database>(Arc::new(RwLock::new(database)));
+        data.insert::<RateLimits>(Arc::new(Mutex::new(RateLimiter::default())));
     }
     if let Err(why) = client.start().await {
         println!("An error occurred while running the client: {:?}", why);
@@ -0,0 +1,251 @@
+// Lightweight inline markup users can put in their messages:
+// [voice:ttsmp3-Brian]...[/voice], [pause 500ms], [slow]...[/slow] and [fast]...[/fast].
+// SSML pauses and rates become the same segments as the markup above. Other SSML tags on the allowlist below are
+// kept as tags, so the text between them is still normalized and filtered, and sent on to the voices that understand
+// them. Anything else that looks like a tag is read as text.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use crate::pipeline::{Rate, Segment};
+
+/// Longest pause a message can ask for, so nobody can hold the channel hostage
+const MAX_PAUSE_MS: u32 = 5000;
+/// Longest all the pauses in a message can add up to
+const MAX_TOTAL_PAUSE_MS: u32 = 10000;
+
+/// SSML tags passed on to the voices that understand them, with the attributes they keep: Amazon Polly's for ttsmp3,
+/// and SAPI's own. Tags that make a voice say something that isn't in the text, like `sub` and `phoneme`, are left out,
+/// since the filter couldn't check it.
+const POLLY_TAGS: &[(&str, &[&str])] = &[
+    ("emphasis", &["level"]),
+    ("say-as", &["interpret-as", "format", "detail"]),
+    ("lang", &["xml:lang"]),
+    ("p", &[]),
+    ("s", &[]),
+    ("w", &["role"]),
+    ("prosody", &["volume", "pitch"]),
+    ("amazon:effect", &["name", "phonation", "vocal-tract-length"]),
+];
+const SAPI_TAGS: &[(&str, &[&str])] = &[
+    ("emph", &[]),
+    ("pitch", &["middle", "absmiddle"]),
+    ("volume", &["level"]),
+    ("spell", &[]),
+    ("partofsp", &["part"]),
+];
+/// Tags turned into segments, or dropped like `speak`, which the backends add themselves
+const SPECIAL_TAGS: &[&str] = &["speak", "break", "silence", "rate"];
+
+lazy_static! {
+    static ref TAG: Regex = Regex::new(r"(?i)\[(/?)(voice|pause|slow|fast)(?:[: ]\s*([^\]]*))?\]").unwrap();
+    static ref SSML_TAG: Regex = {
+        let names = SPECIAL_TAGS.iter()
+            .chain(POLLY_TAGS.iter().chain(SAPI_TAGS).map(|(name, _)| name))
+            .map(|name| regex::escape(name))
+            .collect::<Vec<String>>();
+        Regex::new(&format!(r#"(?i)<(/?)({})((?:\s+[\w:-]+\s*=\s*(?:"[^"<>]*"|'[^'<>]*'))*)\s*(/?)>"#, names.join("|"))).unwrap()
+    };
+    static ref ATTRIBUTE: Regex = Regex::new(r#"([\w:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+    static ref DURATION: Regex = Regex::new(r"(?i)^(\d+(?:\.\d+)?)\s*(ms|s)?$").unwrap();
+}
+
+/// An SSML tag from a message, with only the attributes the allowlist keeps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    /// One of SAPI's tags rather than Polly's
+    pub sapi: bool,
+    pub attributes: Vec<(String, String)>,
+    pub closing: bool,
+    /// Self-closing, like SAPI's `<pitch middle="5"/>` which lasts until the end of the text
+    pub empty: bool,
+}
+
+impl Tag {
+    pub fn markup(&self) -> String {
+        if self.closing {
+            return format!("</{}>", self.name);
+        }
+        let attributes = self.attributes.iter()
+            .map(|(key, value)| format!(" {}=\"{}\"", key, value))
+            .collect::<String>();
+        format!("<{}{}{}>", self.name, attributes, if self.empty { "/" } else { "" })
+    }
+}
+
+/// Split a message into segments. Markup that can't be understood is read as-is.
+/// SSML tags are closed in the right order, and closing tags that don't match an open one are dropped.
+pub fn parse(text: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    // Open SSML tags, with the segment their closing tag becomes
+    let mut open = Vec::new();
+    let mut last = 0;
+    for caps in TAG.captures_iter(text) {
+        let tag = caps.get(0).unwrap();
+        let closing = !caps[1].is_empty();
+        let argument = caps.get(3).map(|m| m.as_str().trim()).filter(|a| !a.is_empty());
+        let segment = match (caps[2].to_lowercase().as_str(), closing, argument) {
+            ("voice", false, Some(voice)) => Segment::Voice(Some(voice.to_string())),
+            ("voice", true, None) => Segment::Voice(None),
+            ("pause", false, Some(duration)) => match parse_duration(duration) {
+                Some(ms) => Segment::Pause(ms),
+                None => continue,
+            },
+            ("slow", false, None) => Segment::Rate(Rate::Slow),
+            ("fast", false, None) => Segment::Rate(Rate::Fast),
+            ("slow", true, None) | ("fast", true, None) => Segment::Rate(Rate::Normal),
+            _ => continue,
+        };
+        push_speech(&mut segments, &mut open, &text[last..tag.start()]);
+        segments.push(segment);
+        last = tag.end();
+    }
+    push_speech(&mut segments, &mut open, &text[last..]);
+    let mut budget = MAX_TOTAL_PAUSE_MS;
+    segments.retain_mut(|segment| match segment {
+        Segment::Pause(ms) => {
+            *ms = (*ms).min(budget);
+            budget -= *ms;
+            *ms > 0
+        }
+        _ => true,
+    });
+    segments
+}
+
+fn push_speech(segments: &mut Vec<Segment>, open: &mut Vec<(String, Segment)>, text: &str) {
+    let mut last = 0;
+    for caps in SSML_TAG.captures_iter(text) {
+        let tag = caps.get(0).unwrap();
+        push_text(segments, &text[last..tag.start()]);
+        last = tag.end();
+        let name = caps[2].to_lowercase();
+        if !caps[1].is_empty() {
+            // Tags opened after this one are closed first
+            if let Some(i) = open.iter().rposition(|(open, _)| *open == name) {
+                segments.extend(open.drain(i..).rev().map(|(_, closing)| closing));
+            }
+            continue;
+        }
+        let empty = !caps[4].is_empty();
+        if let Some((segment, closing)) = ssml_segment(&name, &caps[3], empty) {
+            segments.push(segment);
+            if let Some(closing) = closing {
+                open.push((name, closing));
+            }
+        }
+    }
+    push_text(segments, &text[last..]);
+}
+
+fn push_text(segments: &mut Vec<Segment>, text: &str) {
+    let text = text.trim();
+    if !text.is_empty() {
+        segments.push(Segment::Speech(text.to_string()));
+    }
+}
+
+/// The segment for an opening SSML tag written in a message, and the one its closing tag becomes unless it's `empty`
+fn ssml_segment(name: &str, attributes: &str, empty: bool) -> Option<(Segment, Option<Segment>)> {
+    let attributes = ATTRIBUTE.captures_iter(attributes)
+        .map(|caps| (caps[1].to_lowercase(), caps.get(2).or_else(|| caps.get(3)).unwrap().as_str().trim().to_string()))
+        .collect::<Vec<(String, String)>>();
+    let attribute = |key: &str| attributes.iter().find(|(k, _)| k == key).map(|(_, value)| value.to_lowercase());
+    let rate = |rate| Some((Segment::Rate(rate), if empty { None } else { Some(Segment::Rate(Rate::Normal)) }));
+    match name {
+        "speak" => None,
+        "break" => parse_duration(&attribute("time")?).map(|ms| (Segment::Pause(ms), None)),
+        "silence" => parse_duration(&attribute("msec")?).map(|ms| (Segment::Pause(ms), None)),
+        "rate" => match attribute("speed")?.parse::<i32>().ok()? {
+            speed if speed < 0 => rate(Rate::Slow),
+            speed if speed > 0 => rate(Rate::Fast),
+            _ => rate(Rate::Normal),
+        },
+        "prosody" if matches!(attribute("rate").as_deref(), Some("slow" | "x-slow")) => rate(Rate::Slow),
+        "prosody" if matches!(attribute("rate").as_deref(), Some("fast" | "x-fast")) => rate(Rate::Fast),
+        _ => {
+            let (sapi, allowed) = POLLY_TAGS.iter().map(|tag| (false, tag))
+                .chain(SAPI_TAGS.iter().map(|tag| (true, tag)))
+                .find(|(_, (tag, _))| *tag == name)
+                .map(|(sapi, (_, allowed))| (sapi, *allowed))?;
+            // Quotes and angle brackets can't get this far, and without `&` values can't hold entities either
+            let attributes = attributes.into_iter()
+                .filter(|(key, value)| allowed.contains(&key.as_str()) && !value.contains('&'))
+                .collect();
+            let tag = Tag { name: name.to_string(), sapi, attributes, closing: false, empty };
+            let closing = Tag { attributes: Vec::new(), closing: true, empty: false, ..tag.clone() };
+            Some((Segment::Ssml(tag), if empty { None } else { Some(Segment::Ssml(closing)) }))
+        }
+    }
+}
+
+/// Parse "500ms", "1.5s" or a bare number of milliseconds
+fn parse_duration(duration: &str) -> Option<u32> {
+    let caps = DURATION.captures(duration)?;
+    let value: f32 = caps[1].parse().ok()?;
+    let ms = match caps.get(2).map(|m| m.as_str().to_lowercase()) {
+        Some(unit) if unit == "s" => value * 1000.0,
+        _ => value,
+    };
+    Some((ms as u32).min(MAX_PAUSE_MS))
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("hello world"), vec![Segment::Speech("hello world".to_string())]);
+        assert_eq!(
+            parse("hi [pause 500ms] [voice:ttsmp3-Brian]I am [slow]Brian[/slow][/voice] bye"),
+            vec![
+                Segment::Speech("hi".to_string()),
+                Segment::Pause(500),
+                Segment::Voice(Some("ttsmp3-Brian".to_string())),
+                Segment::Speech("I am".to_string()),
+                Segment::Rate(Rate::Slow),
+                Segment::Speech("Brian".to_string()),
+                Segment::Rate(Rate::Normal),
+                Segment::Voice(None),
+                Segment::Speech("bye".to_string()),
+            ]
+        );
+        assert_eq!(parse("[pause:2s]"), vec![Segment::Pause(2000)]);
+        // Not markup, read as-is
+        assert_eq!(parse("[pause forever] [link]"), vec![Segment::Speech("[pause forever] [link]".to_string())]);
+    }
+
+    /// Segments written out, with SSML tags as markup
+    fn show(text: &str) -> Vec<String> {
+        parse(text).into_iter().map(|segment| match segment {
+            Segment::Speech(text) => text,
+            Segment::Ssml(tag) => tag.markup(),
+            segment => format!("{:?}", segment),
+        }).collect()
+    }
+
+    #[test]
+    fn test_ssml() {
+        assert_eq!(
+            show(r#"<speak>hi <break time="600s"/> <prosody rate="x-slow">there</prosody> <emphasis level='strong'>you</emphasis></speak>"#),
+            vec!["hi", "Pause(5000)", "Rate(Slow)", "there", "Rate(Normal)", "<emphasis level=\"strong\">", "you", "</emphasis>"]
+        );
+        assert_eq!(show("<silence msec='250'/><pitch middle=\"5\"/>hi"), vec!["Pause(250)", "<pitch middle=\"5\"/>", "hi"]);
+        // Attributes that aren't on the allowlist are dropped
+        assert_eq!(
+            show(r#"<prosody pitch="+5%" onload="x" volume="&amp;">a</prosody>"#),
+            vec!["<prosody pitch=\"+5%\">", "a", "</prosody>"]
+        );
+        // Tags are closed in order, and stray closing tags are dropped
+        assert_eq!(show("<emphasis><s>a</emphasis></s>b"), vec!["<emphasis>", "<s>", "a", "</s>", "</emphasis>", "b"]);
+        // Only tags on the allowlist are tags
+        assert_eq!(show("a<b and c>d <b>e</b>"), vec!["a<b and c>d <b>e</b>"]);
+        assert_eq!(show(r#"<sub alias="x">y</sub>"#), vec![r#"<sub alias="x">y</sub>"#]);
+        // Pauses are capped for the whole message, not just one at a time
+        assert_eq!(
+            show("a [pause 5s] b <break time=\"5s\"/> c [pause 5s] d"),
+            vec!["a", "Pause(5000)", "b", "Pause(5000)", "c", "d"]
+        );
+    }
+}
@@ -0,0 +1,355 @@
+// Text normalization, run on every message before it is sent to a TTS backend.
+// Backends like SAPI and espeak read "brb" letter by letter and "$5" as "dollar five",
+// so we expand chat shorthand, numbers, currency, times and dates into words first.
+
+use std::collections::HashMap;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// A set of expansion rules for a single language.
+pub struct Ruleset {
+    pub language: &'static str,
+    abbreviations: &'static [(&'static str, &'static str)],
+    // Number, currency, time and date expansion is only implemented for English
+    expand_numbers: bool,
+}
+
+static ENGLISH: Ruleset = Ruleset {
+    language: "en",
+    abbreviations: &[
+        ("lol", "laughing out loud"),
+        ("lmao", "laughing my ass off"),
+        ("rofl", "rolling on the floor laughing"),
+        ("brb", "be right back"),
+        ("idk", "I don't know"),
+        ("idc", "I don't care"),
+        ("imo", "in my opinion"),
+        ("imho", "in my humble opinion"),
+        ("tbh", "to be honest"),
+        ("btw", "by the way"),
+        ("omg", "oh my god"),
+        ("afaik", "as far as I know"),
+        ("iirc", "if I recall correctly"),
+        ("ngl", "not gonna lie"),
+        ("smh", "shaking my head"),
+        ("irl", "in real life"),
+        ("rn", "right now"),
+        ("ikr", "I know, right"),
+        ("nvm", "never mind"),
+        ("np", "no problem"),
+        ("ty", "thank you"),
+        ("tysm", "thank you so much"),
+        ("thx", "thanks"),
+        ("pls", "please"),
+        ("plz", "please"),
+        ("gg", "good game"),
+        ("gn", "good night"),
+        ("afk", "away from keyboard"),
+        ("jk", "just kidding"),
+        ("ofc", "of course"),
+        ("wdym", "what do you mean"),
+        ("u", "you"),
+        ("ur", "your"),
+        ("w/", "with"),
+        ("w/o", "without"),
+        ("b/c", "because"),
+        ("&", "and"),
+    ],
+    expand_numbers: true,
+};
+
+static JAPANESE: Ruleset = Ruleset {
+    language: "ja",
+    abbreviations: &[
+        ("w", "笑"),
+        ("ww", "笑"),
+        ("www", "笑"),
+        ("kwsk", "詳しく"),
+        ("orz", "がっくり"),
+    ],
+    expand_numbers: false,
+};
+
+// Languages without a ruleset only get server overrides applied
+static EMPTY: Ruleset = Ruleset {
+    language: "",
+    abbreviations: &[],
+    expand_numbers: false,
+};
+
+/// Get the default ruleset for a language code, e.g. `en` or `ja`.
+pub fn ruleset(language: &str) -> &'static Ruleset {
+    match language {
+        "en" => &ENGLISH,
+        "ja" => &JAPANESE,
+        _ => &EMPTY,
+    }
+}
+
+lazy_static! {
+    static ref CURRENCY: Regex = Regex::new(r"^([$€£¥])(\d[\d,]*)(?:\.(\d{1,2}))?$").unwrap();
+    static ref PERCENT: Regex = Regex::new(r"^(-?\d[\d,]*(?:\.\d+)?)%$").unwrap();
+    static ref TIME: Regex = Regex::new(r"^(?i)(\d{1,2}):(\d{2})(am|pm)?$").unwrap();
+    static ref HOUR: Regex = Regex::new(r"^(?i)(\d{1,2})(am|pm)$").unwrap();
+    static ref ISO_DATE: Regex = Regex::new(r"^(\d{4})-(\d{2})-(\d{2})$").unwrap();
+    static ref US_DATE: Regex = Regex::new(r"^(\d{1,2})/(\d{1,2})/(\d{4})$").unwrap();
+    static ref ORDINAL: Regex = Regex::new(r"^(?i)(\d+)(st|nd|rd|th)$").unwrap();
+    static ref NUMBER: Regex = Regex::new(r"^(-)?(\d{1,3}(?:,\d{3})+|\d+)(?:\.(\d+))?$").unwrap();
+}
+
+const ONES: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+/// Expand a message into speakable words.
+/// `overrides` maps lowercase words to their replacement and takes priority over the default ruleset.
+pub fn normalize(text: &str, language: &str, overrides: &HashMap<String, String>) -> String {
+    let rules = ruleset(language);
+    text.split_whitespace()
+        .map(|word| expand_word(word, rules, overrides))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn expand_word(word: &str, rules: &Ruleset, overrides: &HashMap<String, String>) -> String {
+    // Leave links alone, they are not worth mangling
+    if word.contains("://") {
+        return word.to_string();
+    }
+    if let Some(expanded) = expand_token(word, rules, overrides) {
+        return expanded;
+    }
+    // Try again without surrounding punctuation, so "lol," still becomes "laughing out loud,"
+    let start = word.find(|c: char| !is_edge_punctuation(c)).unwrap_or(word.len());
+    let end = word.rfind(|c: char| !is_edge_punctuation(c)).map(|i| i + word[i..].chars().next().unwrap().len_utf8()).unwrap_or(start);
+    if start >= end || (start == 0 && end == word.len()) {
+        return word.to_string();
+    }
+    match expand_token(&word[start..end], rules, overrides) {
+        Some(expanded) => format!("{}{}{}", &word[..start], expanded, &word[end..]),
+        None => word.to_string(),
+    }
+}
+
+fn is_edge_punctuation(c: char) -> bool {
+    matches!(c, '.' | ',' | '!' | '?' | ';' | ':' | '(' | ')' | '"' | '\'' | '*' | '_' | '~')
+}
+
+fn expand_token(token: &str, rules: &Ruleset, overrides: &HashMap<String, String>) -> Option<String> {
+    let lower = token.to_lowercase();
+    if let Some(replacement) = overrides.get(&lower) {
+        return Some(replacement.clone());
+    }
+    if let Some((_, expansion)) = rules.abbreviations.iter().find(|(abbr, _)| *abbr == lower) {
+        return Some(expansion.to_string());
+    }
+    if rules.expand_numbers {
+        return expand_english_number(token);
+    }
+    None
+}
+
+fn expand_english_number(token: &str) -> Option<String> {
+    if let Some(caps) = CURRENCY.captures(token) {
+        let (single, plural, sub_single, sub_plural) = match &caps[1] {
+            "$" => ("dollar", "dollars", "cent", "cents"),
+            "€" => ("euro", "euros", "cent", "cents"),
+            "£" => ("pound", "pounds", "penny", "pence"),
+            _ => ("yen", "yen", "sen", "sen"),
+        };
+        let whole = parse_grouped(&caps[2])?;
+        let mut spoken = format!("{} {}", cardinal(whole), if whole == 1 { single } else { plural });
+        if let Some(fraction) = caps.get(3) {
+            // ".5" means fifty cents, not five
+            let mut cents: u64 = fraction.as_str().parse().ok()?;
+            if fraction.as_str().len() == 1 {
+                cents *= 10;
+            }
+            if cents > 0 {
+                spoken = format!("{} and {} {}", spoken, cardinal(cents), if cents == 1 { sub_single } else { sub_plural });
+            }
+        }
+        return Some(spoken);
+    }
+    if let Some(caps) = PERCENT.captures(token) {
+        return Some(format!("{} percent", expand_english_number(&caps[1])?));
+    }
+    if let Some(caps) = TIME.captures(token) {
+        let hour: u64 = caps[1].parse().ok()?;
+        let minute: u64 = caps[2].parse().ok()?;
+        if hour > 23 || minute > 59 {
+            return None;
+        }
+        let spoken = match minute {
+            0 if caps.get(3).is_none() && hour > 12 => format!("{} hundred", cardinal(hour)),
+            0 => format!("{} o'clock", cardinal(hour)),
+            1..=9 => format!("{} oh {}", cardinal(hour), cardinal(minute)),
+            _ => format!("{} {}", cardinal(hour), cardinal(minute)),
+        };
+        return Some(match caps.get(3) {
+            Some(meridiem) => format!("{} {}", spoken.trim_end_matches(" o'clock"), spell_meridiem(meridiem.as_str())),
+            None => spoken,
+        });
+    }
+    if let Some(caps) = HOUR.captures(token) {
+        let hour: u64 = caps[1].parse().ok()?;
+        if hour > 12 {
+            return None;
+        }
+        return Some(format!("{} {}", cardinal(hour), spell_meridiem(&caps[2])));
+    }
+    if let Some(caps) = ISO_DATE.captures(token) {
+        return spell_date(caps[1].parse().ok()?, caps[2].parse().ok()?, caps[3].parse().ok()?);
+    }
+    if let Some(caps) = US_DATE.captures(token) {
+        return spell_date(caps[3].parse().ok()?, caps[1].parse().ok()?, caps[2].parse().ok()?);
+    }
+    if let Some(caps) = ORDINAL.captures(token) {
+        return Some(ordinal(caps[1].parse().ok()?));
+    }
+    if let Some(caps) = NUMBER.captures(token) {
+        let whole = parse_grouped(&caps[2])?;
+        let mut spoken = cardinal(whole);
+        if caps.get(1).is_some() {
+            spoken = format!("minus {}", spoken);
+        }
+        if let Some(fraction) = caps.get(3) {
+            let digits = fraction.as_str().chars()
+                .map(|c| ONES[c.to_digit(10).unwrap() as usize])
+                .collect::<Vec<&str>>()
+                .join(" ");
+            spoken = format!("{} point {}", spoken, digits);
+        }
+        return Some(spoken);
+    }
+    None
+}
+
+fn parse_grouped(number: &str) -> Option<u64> {
+    number.replace(',', "").parse().ok()
+}
+
+fn spell_meridiem(meridiem: &str) -> &'static str {
+    if meridiem.eq_ignore_ascii_case("am") { "A M" } else { "P M" }
+}
+
+fn spell_date(year: u64, month: u64, day: u64) -> Option<String> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(format!("{} {}, {}", MONTHS[month as usize - 1], ordinal(day), year_words(year)))
+}
+
+/// Read a year the way people say it: 1999 is "nineteen ninety-nine", 2005 is "two thousand five".
+fn year_words(year: u64) -> String {
+    let (century, rest) = (year / 100, year % 100);
+    if !(11..=99).contains(&century) || (2000..2010).contains(&year) {
+        return cardinal(year);
+    }
+    match rest {
+        0 => format!("{} hundred", cardinal(century)),
+        1..=9 => format!("{} oh {}", cardinal(century), cardinal(rest)),
+        _ => format!("{} {}", cardinal(century), cardinal(rest)),
+    }
+}
+
+/// Spell out a number in English, e.g. 1234 is "one thousand two hundred thirty-four".
+pub fn cardinal(n: u64) -> String {
+    if n < 20 {
+        return ONES[n as usize].to_string();
+    }
+    if n < 100 {
+        return match n % 10 {
+            0 => TENS[(n / 10) as usize].to_string(),
+            ones => format!("{}-{}", TENS[(n / 10) as usize], ONES[ones as usize]),
+        };
+    }
+    if n < 1000 {
+        return match n % 100 {
+            0 => format!("{} hundred", ONES[(n / 100) as usize]),
+            rest => format!("{} hundred {}", ONES[(n / 100) as usize], cardinal(rest)),
+        };
+    }
+    let scales = [
+        (1_000_000_000_000, "trillion"),
+        (1_000_000_000, "billion"),
+        (1_000_000, "million"),
+        (1_000, "thousand"),
+    ];
+    for (scale, name) in scales {
+        if n >= scale {
+            if n / scale >= 1000 {
+                // Too large to be worth reading out, read the digits instead
+                return n.to_string().chars()
+                    .map(|c| ONES[c.to_digit(10).unwrap() as usize])
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+            }
+            return match n % scale {
+                0 => format!("{} {}", cardinal(n / scale), name),
+                rest => format!("{} {} {}", cardinal(n / scale), name, cardinal(rest)),
+            };
+        }
+    }
+    unreachable!()
+}
+
+/// Spell out an ordinal number in English, e.g. 23 is "twenty-third".
+pub fn ordinal(n: u64) -> String {
+    let words = cardinal(n);
+    // Only the last word changes: "twenty-one" -> "twenty-first"
+    let split = words.rfind([' ', '-']).map(|i| i + 1).unwrap_or(0);
+    let (head, last) = words.split_at(split);
+    let last = match last {
+        "one" => "first".to_string(),
+        "two" => "second".to_string(),
+        "three" => "third".to_string(),
+        "five" => "fifth".to_string(),
+        "eight" => "eighth".to_string(),
+        "nine" => "ninth".to_string(),
+        "twelve" => "twelfth".to_string(),
+        word if word.ends_with('y') => format!("{}ieth", &word[..word.len() - 1]),
+        word => format!("{}th", word),
+    };
+    format!("{}{}", head, last)
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_slang() {
+        let overrides = HashMap::new();
+        assert_eq!(normalize("brb, idk lol", "en", &overrides), "be right back, I don't know laughing out loud");
+        assert_eq!(normalize("coming w/ friends", "en", &overrides), "coming with friends");
+    }
+
+    #[test]
+    fn test_numbers() {
+        let overrides = HashMap::new();
+        assert_eq!(normalize("it costs $5.50", "en", &overrides), "it costs five dollars and fifty cents");
+        assert_eq!(normalize("the 21st at 3:05pm", "en", &overrides), "the twenty-first at three oh five P M");
+        assert_eq!(normalize("2022-05-08", "en", &overrides), "May eighth, twenty twenty-two");
+        assert_eq!(normalize("1,234 and -2.5", "en", &overrides), "one thousand two hundred thirty-four and minus two point five");
+        assert_eq!(normalize("50%", "en", &overrides), "fifty percent");
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut overrides = HashMap::new();
+        overrides.insert("lol".to_string(), "lol".to_string());
+        overrides.insert("urusai".to_string(), "oo roo sigh".to_string());
+        assert_eq!(normalize("lol Urusai", "en", &overrides), "lol oo roo sigh");
+        // Japanese voices read digits fine, so numbers are left alone
+        assert_eq!(normalize("12 www", "ja", &overrides), "12 笑");
+    }
+}
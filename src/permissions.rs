@@ -0,0 +1,118 @@
+// Who can run which commands. Members with the server's DJ role can do everything but pick the DJ role.
+
+use serenity::framework::standard::macros::{check, hook};
+use serenity::framework::standard::{Args, CommandOptions, DispatchError, Reason};
+use serenity::model::channel::Message;
+use serenity::model::permissions::Permissions;
+use serenity::prelude::*;
+use log::warn;
+use serenity::model::id::RoleId;
+use crate::config::{PermissionSettings, Scope};
+use crate::settings::Level;
+
+/// Permissions that allow joining and leaving voice channels
+pub const DJ_PERMISSIONS: Permissions = Permissions::from_bits_truncate(Permissions::MOVE_MEMBERS.bits() | Permissions::MANAGE_GUILD.bits());
+pub const DJ_DENIAL: &str = "You need the Move Members or Manage Server permission, or the TTS DJ role, to do that.";
+/// Permissions that allow changing the server's settings
+pub const SETTINGS_PERMISSIONS: Permissions = Permissions::MANAGE_GUILD;
+pub const SETTINGS_DENIAL: &str = "You need the Manage Server permission, or the TTS DJ role, to change the bot's settings.";
+
+/// Whether a member with these permissions and roles has any of the `required` permissions, or the DJ role
+pub async fn has_access(ctx: &Context, scope: Scope, permissions: Permissions, roles: &[RoleId], required: Permissions) -> sqlx::Result<bool> {
+    if permissions.intersects(required) || permissions.administrator() {
+        return Ok(true);
+    }
+    let dj_role = PermissionSettings::from_db(ctx, scope).await?.dj_role;
+    Ok(dj_role.map_or(false, |role| roles.iter().any(|r| r.0 as i64 == role)))
+}
+
+async fn allowed(ctx: &Context, msg: &Message, required: Permissions, denial: &str) -> Result<(), Reason> {
+    let member = match msg.member(ctx).await {
+        Ok(member) => member,
+        Err(e) => return Err(Reason::UserAndLog { user: denial.to_string(), log: format!("Could not get member: {}", e) }),
+    };
+    let permissions = member.permissions(&ctx.cache).unwrap_or_else(|_| Permissions::empty());
+    match has_access(ctx, msg.into(), permissions, &member.roles, required).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Reason::User(denial.to_string())),
+        Err(e) => Err(Reason::UserAndLog { user: denial.to_string(), log: format!("Could not get the DJ role: {}", e) }),
+    }
+}
+
+/// Whether the author of a message can change a setting at `level`: their own always, the channel's and server's
+/// with the settings permissions, and every server's only as the bot's owner
+pub async fn can_set(ctx: &Context, msg: &Message, level: Level) -> Result<(), String> {
+    match level {
+        Level::User => Ok(()),
+        Level::Channel | Level::Server => {
+            if msg.guild_id.is_none() {
+                return Err(format!("{} settings can only be changed in a server.", if level == Level::Channel { "Channel" } else { "Server" }));
+            }
+            allowed(ctx, msg, SETTINGS_PERMISSIONS, SETTINGS_DENIAL).await.map_err(|reason| match reason {
+                Reason::User(user) => user,
+                Reason::UserAndLog { user, log } => {
+                    warn!("set check failed: {}", log);
+                    user
+                }
+                _ => SETTINGS_DENIAL.to_string(),
+            })
+        }
+        Level::Global => match ctx.http.get_current_application_info().await {
+            Ok(info) if info.owner.id == msg.author.id => Ok(()),
+            Ok(_) => Err("Only the bot's owner can change the settings of every server.".to_string()),
+            Err(e) => {
+                warn!("Could not get the application info: {}", e);
+                Err("Could not check whether you own the bot, try again later.".to_string())
+            }
+        },
+    }
+}
+
+/// Whether the author of a message can pick the DJ role, which takes the Manage Server permission itself
+pub async fn can_pick_dj_role(ctx: &Context, msg: &Message) -> Result<(), String> {
+    let denial = "You need the Manage Server permission to pick the DJ role.";
+    let member = msg.member(ctx).await.map_err(|e| {
+        warn!("Could not get member: {}", e);
+        denial.to_string()
+    })?;
+    let permissions = member.permissions(&ctx.cache).unwrap_or_else(|_| Permissions::empty());
+    if permissions.manage_guild() || permissions.administrator() {
+        Ok(())
+    } else {
+        Err(denial.to_string())
+    }
+}
+
+#[check]
+#[name = "Dj"]
+async fn dj_check(ctx: &Context, msg: &Message, _: &mut Args, _: &CommandOptions) -> Result<(), Reason> {
+    allowed(ctx, msg, DJ_PERMISSIONS, DJ_DENIAL).await
+}
+
+#[check]
+#[name = "Settings"]
+async fn settings_check(ctx: &Context, msg: &Message, _: &mut Args, _: &CommandOptions) -> Result<(), Reason> {
+    allowed(ctx, msg, SETTINGS_PERMISSIONS, SETTINGS_DENIAL).await
+}
+
+/// Tell the user why a command didn't run
+#[hook]
+pub async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError, command_name: &str) {
+    let reply = match error {
+        DispatchError::CheckFailed(_, Reason::User(reason)) => reason,
+        DispatchError::CheckFailed(_, Reason::UserAndLog { user, log }) => {
+            warn!("{} check failed: {}", command_name, log);
+            user
+        }
+        DispatchError::LackingPermissions(permissions) => {
+            format!("You need the {} permission to do that.", permissions.get_permission_names().join(", "))
+        }
+        DispatchError::OnlyForGuilds => "This command only works in servers.".to_string(),
+        DispatchError::NotEnoughArguments { min, given } => format!("`{}` needs at least {} arguments, got {}.", command_name, min, given),
+        DispatchError::TooManyArguments { max, given } => format!("`{}` takes at most {} arguments, got {}.", command_name, max, given),
+        _ => return,
+    };
+    if let Err(e) = msg.reply(ctx, reply).await {
+        warn!("Could not reply to {}: {}", command_name, e);
+    }
+}
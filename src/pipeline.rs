@@ -0,0 +1,250 @@
+// Turns the text of a message into a single audio file ready for songbird.
+
+use std::collections::HashMap;
+use anyhow::{anyhow, Result};
+use crate::audio::{self, Effects, Part};
+use crate::config::{Filter, Scope, VoicePolicy};
+use crate::tts::{self, TTS};
+use crate::{markup, normalize, voices};
+use crate::markup::Tag;
+
+/// How long a bleep over a filtered word lasts
+const BLEEP_MS: u32 = 400;
+
+/// Speaking rate set by `[slow]` and `[fast]` markup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rate {
+    Normal,
+    Slow,
+    Fast,
+}
+
+impl Rate {
+    /// Playback tempo used when the backend can't change the rate itself
+    pub fn tempo(&self) -> f32 {
+        match self {
+            Rate::Normal => 1.0,
+            Rate::Slow => 0.8,
+            Rate::Fast => 1.25,
+        }
+    }
+}
+
+/// A piece of a message, synthesized separately and then concatenated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Speech(String),
+    Bleep,
+    Pause(u32),
+    /// Switch to another voice, or back to the message's voice with `None`
+    Voice(Option<String>),
+    Rate(Rate),
+    /// Sent to the voices that understand the tag, and ignored by the others
+    Ssml(Tag),
+}
+
+/// Parse the markup in a message, then normalize and filter every piece of speech.
+/// Returns `None` if the filter says the message shouldn't be read.
+/// `voice` must already be allowed by `policy`, which is only checked for voice switches here.
+pub fn prepare(text: &str, voice: &str, dictionary: &HashMap<String, String>, filter: &Filter, policy: &VoicePolicy) -> Option<Vec<Segment>> {
+    let mut current = voice.to_string();
+    let mut segments = Vec::new();
+    for segment in markup::parse(text) {
+        match segment {
+            Segment::Speech(text) => {
+                let text = normalize::normalize(&text, tts::voice_language(&current), dictionary);
+                segments.extend(filter.apply(&text)?);
+            }
+            Segment::Voice(Some(voice_switch)) => {
+                // Unknown and disallowed voices are ignored, the text is read with the current voice instead
+                if let Some(known) = voices::find(&voice_switch).filter(|known| policy.allows(known.id)) {
+                    current = known.id.to_string();
+                    segments.push(Segment::Voice(Some(current.clone())));
+                }
+            }
+            Segment::Voice(None) => {
+                current = voice.to_string();
+                segments.push(Segment::Voice(None));
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments)
+}
+
+/// Text sent to a backend in a single request
+struct Chunk {
+    voice: String,
+    tempo: f32,
+    pitch: f32,
+    text: String,
+    /// SSML tags from the message opened in the text and not closed yet
+    open: Vec<String>,
+}
+
+enum Piece {
+    Chunk(Chunk),
+    Part(Part),
+}
+
+/// Group segments into as few backend requests as possible.
+/// Pauses and rate changes become markup for backends that support SSML, and separate audio otherwise.
+/// The user's rate and pitch are left to ffmpeg for backends that can't do them natively.
+/// Every request gets the SSML tags from the message that are open at that point, so each one is valid on its own.
+fn plan(voice: &str, segments: &[Segment], effects: &Effects) -> Vec<Piece> {
+    let mut current = voice.to_string();
+    let mut rate = Rate::Normal;
+    let mut open: Vec<&Tag> = Vec::new();
+    let mut pieces: Vec<Piece> = Vec::new();
+    for segment in segments {
+        match segment {
+            Segment::Speech(text) => {
+                let ssml = tts::supports_ssml(&current);
+                let (text, tempo, pitch) = if ssml {
+                    // Only tags that went through `markup` are read as SSML
+                    (tts::ssml_rate(&current, rate, &tts::escape_ssml(text)), 1.0, 0.0)
+                } else {
+                    (text.clone(), rate.tempo() * effects.rate, effects.pitch)
+                };
+                if text.is_empty() {
+                    continue;
+                }
+                if let Some(Piece::Chunk(chunk)) = pieces.last_mut() {
+                    if chunk.voice == current && chunk.tempo == tempo {
+                        chunk.text.push(' ');
+                        chunk.text.push_str(&text);
+                        continue;
+                    }
+                }
+                let mut chunk = Chunk { voice: current.clone(), tempo, pitch, text: String::new(), open: Vec::new() };
+                for tag in open.iter().filter(|tag| tts::accepts_tag(&current, tag)) {
+                    chunk.text.push_str(&tag.markup());
+                    if !tag.empty {
+                        chunk.open.push(tag.name.clone());
+                    }
+                }
+                chunk.text.push_str(&text);
+                push_piece(&mut pieces, Piece::Chunk(chunk));
+            }
+            Segment::Pause(ms) => {
+                if let Some(Piece::Chunk(chunk)) = pieces.last_mut() {
+                    if chunk.voice == current && tts::supports_ssml(&current) {
+                        chunk.text.push_str(&tts::ssml_pause(&current, *ms));
+                        continue;
+                    }
+                }
+                push_piece(&mut pieces, Piece::Part(Part::Silence(*ms)));
+            }
+            Segment::Bleep => push_piece(&mut pieces, Piece::Part(Part::Tone(BLEEP_MS))),
+            Segment::Voice(voice_switch) => current = voice_switch.clone().unwrap_or_else(|| voice.to_string()),
+            Segment::Rate(new_rate) => rate = *new_rate,
+            Segment::Ssml(tag) if tag.closing => {
+                // `markup` only closes the innermost tag
+                if let Some(i) = open.iter().rposition(|open| !open.empty && open.name == tag.name) {
+                    open.remove(i);
+                }
+                if let Some(Piece::Chunk(chunk)) = pieces.last_mut() {
+                    if chunk.voice == current && chunk.open.last() == Some(&tag.name) {
+                        chunk.text.push_str(&tag.markup());
+                        chunk.open.pop();
+                    }
+                }
+            }
+            Segment::Ssml(tag) => {
+                open.push(tag);
+                // Otherwise the tag is added when the next chunk starts
+                if let Some(Piece::Chunk(chunk)) = pieces.last_mut() {
+                    if chunk.voice == current && tts::accepts_tag(&current, tag) {
+                        chunk.text.push_str(&tag.markup());
+                        if !tag.empty {
+                            chunk.open.push(tag.name.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    close_tags(&mut pieces);
+    pieces
+}
+
+/// Add a piece, closing the tags still open in the chunk before it
+fn push_piece(pieces: &mut Vec<Piece>, piece: Piece) {
+    close_tags(pieces);
+    pieces.push(piece);
+}
+
+fn close_tags(pieces: &mut [Piece]) {
+    if let Some(Piece::Chunk(chunk)) = pieces.last_mut() {
+        while let Some(name) = chunk.open.pop() {
+            chunk.text.push_str(&format!("</{}>", name));
+        }
+    }
+}
+
+/// Synthesize the segments of a message with the given voice and effects, returning the path of the audio file.
+/// If `loudness` is set, the audio is normalized to that many LUFS before the user's effects are applied.
+pub async fn synthesize(voice: &str, segments: &[Segment], effects: &Effects, loudness: Option<f32>, scope: Scope) -> Result<String> {
+    let pieces = plan(voice, segments, effects);
+    let post = [loudness.map(audio::loudnorm), effects.post_filter()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>();
+    let post = if post.is_empty() { None } else { Some(post.join(",")) };
+    // A single piece of speech doesn't need to go through ffmpeg
+    if let [Piece::Chunk(chunk)] = pieces.as_slice() {
+        if chunk.tempo == 1.0 && chunk.pitch == 0.0 && post.is_none() {
+            return render(&chunk.voice, &chunk.text, effects, scope).await;
+        }
+    }
+
+    let output = format!("tts/{}", tts::file_name(Some(scope)));
+    let stem = output.trim_end_matches(".mp3").to_string();
+    let mut parts = Vec::new();
+    let mut files = Vec::new();
+    for (i, piece) in pieces.into_iter().enumerate() {
+        match piece {
+            Piece::Chunk(chunk) => {
+                let path = match render(&chunk.voice, &chunk.text, effects, scope).await {
+                    Ok(path) => path,
+                    Err(e) => {
+                        remove_files(&files).await;
+                        return Err(e);
+                    }
+                };
+                // Every download writes to the same file, so move it out of the way
+                let extension = std::path::Path::new(&path).extension().and_then(|e| e.to_str()).unwrap_or("mp3");
+                let part = format!("{}-{}.{}", stem, i, extension);
+                tokio::fs::rename(&path, &part).await?;
+                parts.push(Part::File { path: part.clone(), tempo: chunk.tempo, pitch: chunk.pitch });
+                files.push(part);
+            }
+            Piece::Part(part) => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Err(anyhow!("Nothing to read"));
+    }
+
+    let result = audio::concat(&parts, post.as_deref(), &output).await;
+    remove_files(&files).await;
+    result?;
+    Ok(output)
+}
+
+async fn remove_files(files: &[String]) {
+    for file in files {
+        tokio::fs::remove_file(file).await.ok();
+    }
+}
+
+async fn render(voice: &str, text: &str, effects: &Effects, scope: Scope) -> Result<String> {
+    // Backends with SSML support change the rate and pitch themselves
+    let text = if tts::supports_ssml(voice) {
+        tts::ssml_prosody(voice, effects.rate, effects.pitch, text)
+    } else {
+        text.to_string()
+    };
+    let tts = TTS::request(voice, &text, Some(scope)).await.map_err(|e| anyhow!(e))?;
+    tts.download().await
+}
@@ -0,0 +1,115 @@
+// Command prefixes: the bot's own, per server, and other bots' commands that shouldn't be read aloud.
+
+use std::fmt;
+use std::str::FromStr;
+use regex::{Regex, RegexBuilder};
+
+pub const DEFAULT_PREFIX: &str = "tts!";
+pub const MAX_PREFIX_LENGTH: usize = 10;
+/// Keeps user supplied patterns from compiling into something huge
+const MAX_PATTERN_SIZE: usize = 1 << 16;
+
+/// A message that isn't read: either anything starting with a prefix, or anything matching a `/regex/`
+#[derive(Debug, Clone)]
+pub enum Ignore {
+    Prefix(String),
+    Pattern(Regex),
+}
+
+impl Ignore {
+    pub fn matches(&self, content: &str) -> bool {
+        match self {
+            Ignore::Prefix(prefix) => content.starts_with(prefix.as_str()),
+            Ignore::Pattern(pattern) => pattern.is_match(content),
+        }
+    }
+}
+
+impl FromStr for Ignore {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.len() > 2 && s.starts_with('/') && s.ends_with('/') {
+            RegexBuilder::new(&s[1..s.len() - 1])
+                .size_limit(MAX_PATTERN_SIZE)
+                .build()
+                .map(Ignore::Pattern)
+                .map_err(|e| format!("Invalid regex: {}", e))
+        } else if s.is_empty() {
+            Err("Expected a prefix or a `/regex/`".to_string())
+        } else {
+            Ok(Ignore::Prefix(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for Ignore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ignore::Prefix(prefix) => write!(f, "{}", prefix),
+            Ignore::Pattern(pattern) => write!(f, "/{}/", pattern.as_str()),
+        }
+    }
+}
+
+impl PartialEq for Ignore {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+/// Check that a command prefix can be typed and doesn't swallow normal messages
+pub fn validate(prefix: &str) -> Result<(), String> {
+    if prefix.is_empty() || prefix.chars().any(char::is_whitespace) {
+        return Err("The prefix can't be empty or contain spaces".to_string());
+    }
+    if prefix.chars().count() > MAX_PREFIX_LENGTH {
+        return Err(format!("The prefix can be at most {} characters long", MAX_PREFIX_LENGTH));
+    }
+    if prefix.chars().all(char::is_alphanumeric) {
+        return Err("The prefix needs at least one symbol, or normal messages would be taken as commands".to_string());
+    }
+    Ok(())
+}
+
+/// Whether a message is a command, for this bot or another, rather than something to read
+pub fn is_ignored(content: &str, prefix: &str, ignored: &[Ignore]) -> bool {
+    content.starts_with(prefix) || ignored.iter().any(|ignore| ignore.matches(content))
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("!".parse::<Ignore>().unwrap(), Ignore::Prefix("!".to_string()));
+        assert!(matches!("/^[-$]\\w+/".parse::<Ignore>().unwrap(), Ignore::Pattern(_)));
+        assert_eq!("/^[-$]\\w+/".parse::<Ignore>().unwrap().to_string(), "/^[-$]\\w+/");
+        assert!("/(/".parse::<Ignore>().is_err());
+        assert!(" ".parse::<Ignore>().is_err());
+        // Too short to be a regex
+        assert_eq!("//".parse::<Ignore>().unwrap(), Ignore::Prefix("//".to_string()));
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let ignored = ["!".parse().unwrap(), "/^-\\w+/".parse().unwrap()];
+        assert!(is_ignored("tts!join", DEFAULT_PREFIX, &ignored));
+        assert!(is_ignored("!play song", DEFAULT_PREFIX, &ignored));
+        assert!(is_ignored("-skip", DEFAULT_PREFIX, &ignored));
+        assert!(!is_ignored("- a list item", DEFAULT_PREFIX, &ignored));
+        assert!(!is_ignored("hello!", DEFAULT_PREFIX, &ignored));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("?").is_ok());
+        assert!(validate("tts!").is_ok());
+        assert!(validate("").is_err());
+        assert!(validate("a b!").is_err());
+        assert!(validate("tts").is_err());
+        assert!(validate("waytoolong!").is_err());
+    }
+}
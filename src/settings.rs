@@ -0,0 +1,257 @@
+// Typed settings that can be set for a user, a channel, a server or every server. When a setting is read,
+// the most specific level that has a value wins, and the key's default is used if none has one.
+
+use std::fmt;
+use std::str::FromStr;
+use crate::audio::LOUDNESS_RANGE;
+use crate::{prefix, voices};
+
+/// Where a setting is stored, from the most to the least specific
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Level {
+    User,
+    Channel,
+    Server,
+    Global,
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [Level::User, Level::Channel, Level::Server, Level::Global];
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Level::User => "user",
+            Level::Channel => "channel",
+            Level::Server => "server",
+            Level::Global => "global",
+        })
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Level::ALL.iter()
+            .find(|level| level.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("Unknown level `{}`, expected `user`, `channel`, `server` or `global`", s))
+    }
+}
+
+/// A setting with a value of type `T`
+pub struct Key<T> {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Levels the setting can be set at, from the most to the least specific
+    pub levels: &'static [Level],
+    pub default: &'static str,
+    parse: fn(&str) -> Result<T, String>,
+    show: fn(&T) -> String,
+}
+
+impl<T> Key<T> {
+    pub fn parse(&self, value: &str) -> Result<T, String> {
+        (self.parse)(value.trim())
+    }
+
+    /// How a value is stored
+    pub fn show(&self, value: &T) -> String {
+        (self.show)(value)
+    }
+
+    /// The value from the most specific level that has a valid one, or the default.
+    /// `lookup` returns the raw value stored at a level.
+    pub fn resolve<'a>(&self, lookup: impl Fn(Level) -> Option<&'a str>) -> T {
+        self.levels.iter()
+            .filter_map(|level| lookup(*level))
+            .find_map(|value| self.parse(value).ok())
+            .unwrap_or_else(|| self.parse(self.default).expect("setting defaults are valid"))
+    }
+}
+
+/// A setting of any type, for the generic `set` and `reset` commands
+pub trait Setting: Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn levels(&self) -> &'static [Level];
+    fn default(&self) -> &'static str;
+    /// Validate a value, returning how it is stored
+    fn normalize(&self, value: &str) -> Result<String, String>;
+
+    /// The stored value from the most specific level that has a valid one, or the default, see `Key::resolve`
+    fn effective<'a>(&self, lookup: &dyn Fn(Level) -> Option<&'a str>) -> String {
+        self.levels().iter()
+            .filter_map(|level| lookup(*level))
+            .find_map(|value| self.normalize(value).ok())
+            .unwrap_or_else(|| self.default().to_string())
+    }
+}
+
+impl<T> Setting for Key<T> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn levels(&self) -> &'static [Level] {
+        self.levels
+    }
+
+    fn default(&self) -> &'static str {
+        self.default
+    }
+
+    fn normalize(&self, value: &str) -> Result<String, String> {
+        self.parse(value).map(|value| (self.show)(&value))
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" => Ok(true),
+        "off" | "false" | "no" => Ok(false),
+        _ => Err("Expected `on` or `off`".to_string()),
+    }
+}
+
+fn show_bool(value: &bool) -> String {
+    if *value { "on" } else { "off" }.to_string()
+}
+
+fn parse_range(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(value) if value >= min && value <= max => Ok(value),
+        _ => Err(format!("Expected a whole number between {} and {}", min, max)),
+    }
+}
+
+pub static PREFIX: Key<String> = Key {
+    name: "prefix",
+    description: "The bot's command prefix",
+    levels: &[Level::Channel, Level::Server, Level::Global],
+    default: prefix::DEFAULT_PREFIX,
+    parse: |value| prefix::validate(value).map(|_| value.to_string()),
+    show: |value| value.clone(),
+};
+
+pub static VOLUME: Key<u32> = Key {
+    name: "volume",
+    description: "Master volume in percent, 0 to 200",
+    levels: &[Level::Channel, Level::Server, Level::Global],
+    default: "100",
+    parse: |value| parse_range(value.trim_end_matches('%'), 0, 200),
+    show: u32::to_string,
+};
+
+pub static LOUDNESS: Key<Option<f32>> = Key {
+    name: "loudness",
+    description: "Loudness every message is normalized to in LUFS, or `off`",
+    levels: &[Level::Server, Level::Global],
+    default: "-16",
+    parse: |value| {
+        if value.eq_ignore_ascii_case("off") {
+            return Ok(None);
+        }
+        let (min, max) = LOUDNESS_RANGE;
+        match value.parse::<f32>() {
+            Ok(loudness) if loudness >= min && loudness <= max => Ok(Some(loudness)),
+            _ => Err(format!("Expected `off` or a loudness between {} and {} LUFS", min, max)),
+        }
+    },
+    show: |value| value.map_or_else(|| "off".to_string(), |loudness| loudness.to_string()),
+};
+
+pub static MAX_LENGTH: Key<u32> = Key {
+    name: "max_length",
+    description: "Characters read from each message, 0 for no limit",
+    levels: &[Level::Channel, Level::Server, Level::Global],
+    default: "300",
+    parse: |value| parse_range(value, 0, 2000),
+    show: u32::to_string,
+};
+
+pub static COLLAPSE: Key<bool> = Key {
+    name: "collapse",
+    description: "Collapse repeated characters and words",
+    levels: &[Level::Channel, Level::Server, Level::Global],
+    default: "on",
+    parse: parse_bool,
+    show: show_bool,
+};
+
+pub static DETECT_LANGUAGE: Key<bool> = Key {
+    name: "detect_language",
+    description: "Switch to the voice mapped with `langvoice` when a message is in another language",
+    levels: &[Level::User, Level::Channel, Level::Server, Level::Global],
+    default: "on",
+    parse: parse_bool,
+    show: show_bool,
+};
+
+/// The voice users get until they pick their own. There is no user level: a user's own voice can be one of the
+/// server's presets, which this can't validate, so it stays with `setvoice` and the `users` table
+pub static VOICE: Key<String> = Key {
+    name: "voice",
+    description: "Default voice for users who didn't pick one with `setvoice`",
+    levels: &[Level::Channel, Level::Server, Level::Global],
+    default: voices::DEFAULT_VOICE,
+    parse: |value| voices::find(value).map(|voice| voice.id.to_string()).ok_or_else(|| format!("Unknown voice `{}`", value)),
+    show: |value| value.clone(),
+};
+
+/// Every setting, in the order `set` lists them
+pub static REGISTRY: &[&dyn Setting] = &[&PREFIX, &VOICE, &VOLUME, &LOUDNESS, &MAX_LENGTH, &COLLAPSE, &DETECT_LANGUAGE];
+
+pub fn find(name: &str) -> Option<&'static dyn Setting> {
+    REGISTRY.iter().find(|setting| setting.name().eq_ignore_ascii_case(name)).copied()
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        for setting in REGISTRY {
+            assert!(setting.normalize(setting.default()).is_ok(), "{}", setting.name());
+            assert!(!setting.levels().is_empty());
+            assert!(setting.levels().windows(2).all(|w| w[0] < w[1]), "{} levels are out of order", setting.name());
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let values = [(Level::Server, "120"), (Level::Global, "80")];
+        let lookup = |level| values.iter().find(|(l, _)| *l == level).map(|(_, v)| *v);
+        assert_eq!(VOLUME.resolve(lookup), 120);
+        assert_eq!(VOLUME.resolve(|level| if level == Level::Global { Some("80") } else { None }), 80);
+        assert_eq!(VOLUME.resolve(|_| None), 100);
+        // Invalid stored values fall through to the next level
+        assert_eq!(VOLUME.resolve(|level| if level == Level::Channel { Some("999") } else { Some("50") }), 50);
+        // Levels the key doesn't have are ignored
+        assert!(LOUDNESS.resolve(|level| if level == Level::Channel { Some("off") } else { None }).is_some());
+        let setting = find("volume").unwrap();
+        assert_eq!(setting.effective(&lookup), "120");
+        assert_eq!(setting.effective(&|_| None), "100");
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(COLLAPSE.normalize("YES").unwrap(), "on");
+        assert_eq!(LOUDNESS.normalize("OFF").unwrap(), "off");
+        assert_eq!(LOUDNESS.normalize("-20").unwrap(), "-20");
+        assert!(LOUDNESS.normalize("0").is_err());
+        assert_eq!(VOLUME.normalize("50%").unwrap(), "50");
+        assert!(PREFIX.normalize("tts").is_err());
+        assert_eq!(VOICE.normalize("TIKTOK-EN_US_002").unwrap(), "tiktok-en_us_002");
+        assert!(VOICE.normalize("tiktok-nobody").is_err());
+        assert_eq!(find("Max_Length").unwrap().name(), "max_length");
+        assert_eq!("Channel".parse::<Level>().unwrap(), Level::Channel);
+    }
+}
@@ -0,0 +1,399 @@
+// Slash command versions of the everyday commands. They share their logic with the prefix commands in handler.rs
+// and reply ephemerally, so they don't clutter the channel that is being read.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde_json::Value;
+use serenity::model::interactions::application_command::{
+    ApplicationCommand, ApplicationCommandInteraction, ApplicationCommandInteractionDataOption, ApplicationCommandOptionType,
+};
+use serenity::model::interactions::autocomplete::AutocompleteInteraction;
+use serenity::model::interactions::{Interaction, InteractionResponseType};
+use serenity::model::permissions::Permissions;
+use serenity::prelude::*;
+use log::{error, info, warn};
+use crate::audio::{Effect, Effects};
+use crate::config::{Presets, Scope, VoicePolicy};
+use crate::handler;
+use crate::permissions::{self, DJ_DENIAL, DJ_PERMISSIONS};
+use crate::voices;
+
+/// Discord only shows this many autocomplete choices
+const MAX_CHOICES: usize = 25;
+/// Discord messages can be at most this long
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// Whether the slash commands were registered since the bot started
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Register the slash commands, replacing any that were registered before. They are global, so this only runs once
+/// per process rather than on every reconnect, unless it failed
+pub async fn register(ctx: &Context) {
+    if REGISTERED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let result = ApplicationCommand::set_global_application_commands(&ctx.http, |commands| {
+        commands
+            .create_application_command(|c| c.name("join").description("Join your voice channel and read this channel"))
+            .create_application_command(|c| c.name("leave").description("Leave the voice channel"))
+            .create_application_command(|c| {
+                c.name("setvoice").description("Set your TTS voice")
+                    .create_option(|o| {
+                        o.name("voice")
+                            .description("A voice, one of the server's presets, or `default`")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                            .set_autocomplete(true)
+                    })
+                    .create_option(global_option)
+            })
+            .create_application_command(|c| {
+                c.name("voiceopts").description("Change how your voice sounds")
+                    .create_option(|o| {
+                        o.name("show").description("Show your voice options")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(global_option)
+                    })
+                    .create_option(|o| {
+                        o.name("rate").description("Set your speaking rate, 1.0 is normal speed")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("value").description("Rate multiplier")
+                                    .kind(ApplicationCommandOptionType::Number)
+                                    .min_number_value(Effects::RATE_RANGE.0 as f64)
+                                    .max_number_value(Effects::RATE_RANGE.1 as f64)
+                                    .required(true)
+                            })
+                            .create_sub_option(global_option)
+                    })
+                    .create_option(|o| {
+                        o.name("pitch").description("Shift your voice's pitch by a number of semitones")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("value").description("Semitones")
+                                    .kind(ApplicationCommandOptionType::Number)
+                                    .min_number_value(Effects::PITCH_RANGE.0 as f64)
+                                    .max_number_value(Effects::PITCH_RANGE.1 as f64)
+                                    .required(true)
+                            })
+                            .create_sub_option(global_option)
+                    })
+                    .create_option(|o| {
+                        o.name("gain").description("Make your voice louder or quieter")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("value").description("dB")
+                                    .kind(ApplicationCommandOptionType::Number)
+                                    .min_number_value(Effects::GAIN_RANGE.0 as f64)
+                                    .max_number_value(Effects::GAIN_RANGE.1 as f64)
+                                    .required(true)
+                            })
+                            .create_sub_option(global_option)
+                    })
+                    .create_option(|o| {
+                        o.name("effect").description("Add an effect to your voice")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("value").description("Effect")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .add_string_choice("none", "none")
+                                    .add_string_choice("echo", "echo")
+                                    .add_string_choice("radio", "radio")
+                                    .add_string_choice("robot", "robot")
+                                    .required(true)
+                            })
+                            .create_sub_option(global_option)
+                    })
+                    .create_option(|o| {
+                        o.name("reset").description("Reset your voice options")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(global_option)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("alias").description("Set what the bot calls you when someone mentions you, in every server")
+                    .create_option(|o| {
+                        o.name("name").description("Your alias, or `off` to use your username")
+                            .kind(ApplicationCommandOptionType::String)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("optout").description("Stop the bot from reading your messages")
+                    .create_option(global_option)
+            })
+            .create_application_command(|c| {
+                c.name("optin").description("Let the bot read your messages again")
+                    .create_option(global_option)
+            })
+            .create_application_command(|c| {
+                c.name("voices").description("List the available voices")
+                    .create_option(|o| {
+                        o.name("provider").description("Only list this provider's voices")
+                            .kind(ApplicationCommandOptionType::String);
+                        for provider in voices::PROVIDERS {
+                            o.add_string_choice(provider, provider);
+                        }
+                        o
+                    })
+                    .create_option(|o| {
+                        o.name("language").description("Only list voices for this language, e.g. `en` or `ja`")
+                            .kind(ApplicationCommandOptionType::String)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("preview").description("Hear what a voice sounds like before setting it")
+                    .create_option(|o| {
+                        o.name("voice")
+                            .description("A voice, or one of the server's presets")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                            .set_autocomplete(true)
+                    })
+                    .create_option(|o| {
+                        o.name("text").description("What to read instead of the voice's sample text")
+                            .kind(ApplicationCommandOptionType::String)
+                    })
+            })
+            .create_application_command(|c| c.name("settings").description("Show the server's settings, and your own voice and options"))
+    }).await;
+    match result {
+        Ok(commands) => info!("Registered {} slash commands", commands.len()),
+        Err(e) => {
+            warn!("Could not register slash commands: {}", e);
+            REGISTERED.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+fn global_option(
+    o: &mut serenity::builder::CreateApplicationCommandOption,
+) -> &mut serenity::builder::CreateApplicationCommandOption {
+    o.name("global")
+        .description("Change your settings in every server instead of this one")
+        .kind(ApplicationCommandOptionType::Boolean)
+}
+
+fn option<'a>(options: &'a [ApplicationCommandInteractionDataOption], name: &str) -> Option<&'a Value> {
+    options.iter().find(|o| o.name == name).and_then(|o| o.value.as_ref())
+}
+
+/// The `global` option, which is implied in DMs
+fn global(command: &ApplicationCommandInteraction, options: &[ApplicationCommandInteractionDataOption]) -> bool {
+    command.guild_id.is_none() || option(options, "global").and_then(Value::as_bool).unwrap_or(false)
+}
+
+pub async fn handle(ctx: &Context, interaction: Interaction) {
+    match interaction {
+        Interaction::ApplicationCommand(command) if command.data.name == "preview" => preview(ctx, &command).await,
+        Interaction::ApplicationCommand(command) => {
+            let reply = match run(ctx, &command).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    error!("/{} failed: {}", command.data.name, e);
+                    "Something went wrong, try again later.".to_string()
+                }
+            };
+            let result = command.create_interaction_response(&ctx.http, |r| {
+                r.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|d| d.content(reply).ephemeral(true))
+            }).await;
+            if let Err(e) = result {
+                warn!("Could not reply to /{}: {}", command.data.name, e);
+            }
+        }
+        Interaction::Autocomplete(autocomplete) => complete_voice(ctx, &autocomplete).await,
+        // Buttons are handled by the collectors that created them
+        _ => {}
+    }
+}
+
+/// Run a slash command, returning the reply
+async fn run(ctx: &Context, command: &ApplicationCommandInteraction) -> sqlx::Result<String> {
+    let scope = Scope::from(command);
+    let options = &command.data.options;
+    match command.data.name.as_str() {
+        "join" | "leave" => {
+            let member = match &command.member {
+                Some(member) => member,
+                None => return Ok("This command only works in servers.".to_string()),
+            };
+            let permissions = member.permissions.unwrap_or_else(Permissions::empty);
+            if !permissions::has_access(ctx, scope, permissions, &member.roles, DJ_PERMISSIONS).await? {
+                return Ok(DJ_DENIAL.to_string());
+            }
+            if command.data.name == "join" {
+                handler::join_voice(ctx, scope).await
+            } else {
+                handler::leave_voice(ctx, scope).await
+            }
+        }
+        "setvoice" => {
+            let voice = option(options, "voice").and_then(Value::as_str).unwrap_or_default().trim();
+            handler::set_voice(ctx, scope, voice, global(command, options)).await
+        }
+        "voiceopts" => {
+            let subcommand = match options.first() {
+                Some(subcommand) => subcommand,
+                None => return handler::show_effects(ctx, scope, global(command, options)).await,
+            };
+            let global = global(command, &subcommand.options);
+            let value = option(&subcommand.options, "value");
+            let number = value.and_then(Value::as_f64).unwrap_or_default() as f32;
+            Ok(match subcommand.name.as_str() {
+                "rate" => {
+                    handler::change_effects(ctx, scope, global, |effects| effects.rate = number).await?;
+                    format!("Set rate to {}x{}", number, handler::scope_suffix(global))
+                }
+                "pitch" => {
+                    handler::change_effects(ctx, scope, global, |effects| effects.pitch = number).await?;
+                    format!("Set pitch to {:+} semitones{}", number, handler::scope_suffix(global))
+                }
+                "gain" => {
+                    handler::change_effects(ctx, scope, global, |effects| effects.gain = number).await?;
+                    format!("Set gain to {:+} dB{}", number, handler::scope_suffix(global))
+                }
+                "effect" => match value.and_then(Value::as_str).unwrap_or_default().parse::<Effect>() {
+                    Ok(effect) => {
+                        handler::change_effects(ctx, scope, global, |effects| effects.effect = effect).await?;
+                        format!("Set effect to `{}`{}", effect, handler::scope_suffix(global))
+                    }
+                    Err(e) => e,
+                },
+                "reset" => handler::reset_effects(ctx, scope, global).await?,
+                _ => handler::show_effects(ctx, scope, global).await?,
+            })
+        }
+        "alias" => {
+            let alias = option(options, "name").and_then(Value::as_str).unwrap_or_default().trim();
+            handler::set_alias(ctx, scope, alias).await
+        }
+        "optout" => handler::opt_out(ctx, scope, global(command, options)).await,
+        "optin" => handler::opt_in(ctx, scope, global(command, options)).await,
+        "voices" => {
+            let provider = option(options, "provider").and_then(Value::as_str);
+            let language = option(options, "language").and_then(Value::as_str).map(str::to_lowercase);
+            Ok(match handler::voice_lines(provider, language.as_deref()) {
+                Ok(lines) => fit_lines(&lines),
+                Err(e) => e,
+            })
+        }
+        "settings" => {
+            if command.guild_id.is_none() {
+                return Ok("This command only works in servers.".to_string());
+            }
+            let fields = handler::settings_fields(ctx, scope).await?;
+            Ok(fields.into_iter()
+                .map(|(title, value, _)| format!("**{}**\n{}", title, value))
+                .collect::<Vec<String>>()
+                .join("\n\n"))
+        }
+        name => Ok(format!("Unknown command `/{}`", name)),
+    }
+}
+
+/// As many lines as fit in one message, followed by how many didn't
+fn fit_lines(lines: &[String]) -> String {
+    let mut reply = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let rest = format!("…and {} more, pick a provider or language to narrow the list.", lines.len() - i);
+        if reply.len() + line.len() + 1 + rest.len() > MAX_MESSAGE_LENGTH {
+            return reply + &rest;
+        }
+        reply.push_str(line);
+        reply.push('\n');
+    }
+    reply
+}
+
+/// `/preview` can take longer than Discord waits for a reply, so it is deferred and answered with a follow-up,
+/// which has the audio attached if the bot isn't in a voice channel
+async fn preview(ctx: &Context, command: &ApplicationCommandInteraction) {
+    let deferred = command.create_interaction_response(&ctx.http, |r| {
+        r.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|d| d.ephemeral(true))
+    }).await;
+    if let Err(e) = deferred {
+        warn!("Could not reply to /preview: {}", e);
+        return;
+    }
+    let (reply, file) = match run_preview(ctx, command).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            error!("/preview failed: {}", e);
+            ("Something went wrong, try again later.".to_string(), None)
+        }
+    };
+    let result = command.create_followup_message(&ctx.http, |m| {
+        m.content(reply).ephemeral(true);
+        if let Some(file) = &file {
+            m.add_file(file.as_str());
+        }
+        m
+    }).await;
+    if let Err(e) = result {
+        warn!("Could not reply to /preview: {}", e);
+    }
+}
+
+async fn run_preview(ctx: &Context, command: &ApplicationCommandInteraction) -> sqlx::Result<(String, Option<String>)> {
+    let member = match &command.member {
+        Some(member) => member,
+        None => return Ok(("This command only works in servers.".to_string(), None)),
+    };
+    let roles = member.roles.iter().map(|role| role.0 as i64).collect::<Vec<i64>>();
+    let options = &command.data.options;
+    let voice = option(options, "voice").and_then(Value::as_str).unwrap_or_default().trim();
+    let text = option(options, "text").and_then(Value::as_str).unwrap_or_default().trim();
+    let query = format!("{} {}", voice, text);
+    handler::preview_voice(ctx, Scope::from(command), &roles, query.trim()).await
+}
+
+/// Suggest voices, and the server's presets, for whatever the user typed so far
+async fn complete_voice(ctx: &Context, autocomplete: &AutocompleteInteraction) {
+    let query = autocomplete.data.options.iter()
+        .find(|o| o.focused)
+        .and_then(|o| o.value.as_ref())
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let choices = match voice_choices(ctx, Scope::from(autocomplete), &query).await {
+        Ok(choices) => choices,
+        Err(e) => {
+            warn!("Could not suggest voices: {}", e);
+            return;
+        }
+    };
+
+    let result = autocomplete.create_autocomplete_response(&ctx.http, |r| {
+        for (name, value) in choices {
+            r.add_string_choice(name, value);
+        }
+        r
+    }).await;
+    if let Err(e) = result {
+        warn!("Could not send voice suggestions: {}", e);
+    }
+}
+
+/// Presets and allowed voices matching a query, as (name, value) choices
+async fn voice_choices(ctx: &Context, scope: Scope, query: &str) -> sqlx::Result<Vec<(String, String)>> {
+    let mut choices = Vec::new();
+    if scope.guild_id.is_some() {
+        let presets = Presets::from_db(ctx, scope).await?;
+        let mut names = presets.presets.keys()
+            .filter(|name| name.contains(&query.to_lowercase()))
+            .cloned()
+            .collect::<Vec<String>>();
+        names.sort();
+        choices.extend(names.into_iter().map(|name| (format!("{} (preset)", name), name)));
+
+        let policy = VoicePolicy::from_db(ctx, scope).await?;
+        choices.extend(voices::suggest(query).into_iter()
+            .filter(|voice| policy.allows(voice.id))
+            .map(|voice| (format!("{} — {}", voice.id, voice.name), voice.id.to_string())));
+    } else {
+        choices.extend(voices::suggest(query).into_iter()
+            .map(|voice| (format!("{} — {}", voice.id, voice.name), voice.id.to_string())));
+    }
+    choices.truncate(MAX_CHOICES);
+    Ok(choices)
+}
@@ -0,0 +1,189 @@
+// Anti-spam: rate limits and repetition collapsing, so one user can't monopolize the voice channel.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serenity::prelude::*;
+
+/// Runs of the same character longer than this are shortened
+const MAX_CHAR_RUN: usize = 3;
+/// Words or phrases repeated this many times in a row are only read once
+const MIN_PHRASE_REPEATS: usize = 3;
+/// Longest phrase, in words, that is checked for repetition
+const MAX_PHRASE_WORDS: usize = 4;
+/// How often users and servers that stopped talking are forgotten
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A sliding-window rate limit: at most `messages` every `seconds`. Zero disables it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub messages: u32,
+    pub seconds: u32,
+}
+
+/// Recently spoken messages, per user and per server
+#[derive(Default)]
+pub struct RateLimiter {
+    // Each key's window, and when its recent messages were spoken
+    history: HashMap<(i64, Option<i64>), (Duration, VecDeque<Instant>)>,
+    last_sweep: Option<Instant>,
+}
+
+pub struct RateLimits;
+
+impl TypeMapKey for RateLimits {
+    type Value = Arc<Mutex<RateLimiter>>;
+}
+
+impl RateLimiter {
+    /// Record a message and return whether it is within both the user's and the server's limit.
+    /// Messages over the limit are not recorded, so they don't extend the cooldown.
+    pub fn check(&mut self, server_id: i64, user_id: i64, user: Limit, server: Limit) -> bool {
+        self.check_at(Instant::now(), server_id, user_id, user, server)
+    }
+
+    fn check_at(&mut self, now: Instant, server_id: i64, user_id: i64, user: Limit, server: Limit) -> bool {
+        self.sweep(now);
+        let keys = [((server_id, Some(user_id)), user), ((server_id, None), server)];
+        for (key, limit) in keys {
+            if limit.messages == 0 || limit.seconds == 0 {
+                continue;
+            }
+            let window = Duration::from_secs(limit.seconds as u64);
+            let (_, history) = self.history.entry(key).or_insert_with(|| (window, VecDeque::new()));
+            while history.front().is_some_and(|t| now.duration_since(*t) > window) {
+                history.pop_front();
+            }
+            let full = history.len() >= limit.messages as usize;
+            if history.is_empty() {
+                self.history.remove(&key);
+            }
+            if full {
+                return false;
+            }
+        }
+        for (key, limit) in keys {
+            if limit.messages != 0 && limit.seconds != 0 {
+                let window = Duration::from_secs(limit.seconds as u64);
+                let (last_window, history) = self.history.entry(key).or_insert_with(|| (window, VecDeque::new()));
+                *last_window = window;
+                history.push_back(now);
+            }
+        }
+        true
+    }
+
+    /// Forget keys whose messages are all outside their window, so users who stopped talking don't stay in memory
+    fn sweep(&mut self, now: Instant) {
+        if self.last_sweep.is_some_and(|last| now.duration_since(last) < SWEEP_INTERVAL) {
+            return;
+        }
+        self.last_sweep = Some(now);
+        self.history.retain(|_, (window, history)| history.back().is_some_and(|t| now.duration_since(*t) <= *window));
+    }
+}
+
+/// Collapse repeated characters ("aaaaaaa" -> "aaa") and repeated words or phrases
+/// ("lol lol lol lol" -> "lol"). Digits are left alone so numbers survive.
+pub fn collapse(text: &str) -> String {
+    let mut chars = String::with_capacity(text.len());
+    let mut run = 0;
+    let mut last = None;
+    for c in text.chars() {
+        if Some(c) == last {
+            run += 1;
+        } else {
+            run = 1;
+            last = Some(c);
+        }
+        if run <= MAX_CHAR_RUN || c.is_ascii_digit() {
+            chars.push(c);
+        }
+    }
+
+    let words = chars.split_whitespace().collect::<Vec<&str>>();
+    let same = |a: &[&str], b: &[&str]| a.iter().zip(b).all(|(a, b)| a.eq_ignore_ascii_case(b));
+    let mut result = Vec::with_capacity(words.len());
+    let mut i = 0;
+    'outer: while i < words.len() {
+        for n in 1..=MAX_PHRASE_WORDS {
+            let phrase = match words.get(i..i + n) {
+                Some(phrase) => phrase,
+                None => break,
+            };
+            let mut repeats = 1;
+            while words.get(i + repeats * n..i + (repeats + 1) * n).is_some_and(|next| same(phrase, next)) {
+                repeats += 1;
+            }
+            if repeats >= MIN_PHRASE_REPEATS {
+                result.extend_from_slice(phrase);
+                i += repeats * n;
+                continue 'outer;
+            }
+        }
+        result.push(words[i]);
+        i += 1;
+    }
+    result.join(" ")
+}
+
+/// Cut a message down to at most `max` characters, preferring to stop at a word boundary.
+/// A `max` of zero means no limit.
+pub fn truncate(text: &str, max: usize) -> String {
+    if max == 0 || text.chars().count() <= max {
+        return text.to_string();
+    }
+    let cut = text.char_indices().nth(max).map(|(i, _)| i).unwrap_or(text.len());
+    let truncated = &text[..cut];
+    match truncated.rfind(char::is_whitespace) {
+        Some(space) if space > 0 => truncated[..space].to_string(),
+        _ => truncated.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_collapse() {
+        assert_eq!(collapse("aaaaaaaaa"), "aaa");
+        assert_eq!(collapse("lol lol LOL lol"), "lol");
+        assert_eq!(collapse("no no"), "no no");
+        assert_eq!(collapse("go team go team go team yay"), "go team yay");
+        assert_eq!(collapse("it was 1000000"), "it was 1000000");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("hello there world", 13), "hello there");
+        assert_eq!(truncate("hello", 0), "hello");
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut limiter = RateLimiter::default();
+        let user = Limit { messages: 2, seconds: 60 };
+        let server = Limit { messages: 3, seconds: 60 };
+        assert!(limiter.check(1, 1, user, server));
+        assert!(limiter.check(1, 1, user, server));
+        assert!(!limiter.check(1, 1, user, server));
+        assert!(limiter.check(1, 2, user, server));
+        // The server limit applies to everyone
+        assert!(!limiter.check(1, 3, user, server));
+    }
+
+    #[test]
+    fn test_rate_limit_forgets() {
+        let mut limiter = RateLimiter::default();
+        let limit = Limit { messages: 1, seconds: 1 };
+        let start = Instant::now();
+        assert!(limiter.check_at(start, 1, 1, limit, limit));
+        assert!(!limiter.check_at(start, 1, 1, limit, limit));
+        assert_eq!(limiter.history.len(), 2);
+        // Long after the window, everything is forgotten
+        assert!(limiter.check_at(start + SWEEP_INTERVAL * 2, 1, 2, limit, limit));
+        assert_eq!(limiter.history.len(), 2);
+        assert!(!limiter.history.contains_key(&(1, Some(1))));
+    }
+}
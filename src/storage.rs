@@ -0,0 +1,785 @@
+// Where the bot keeps its settings, picked by the scheme of DATABASE_URL: SQLite for a single instance,
+// or PostgreSQL to share state between several instances.
+// Queries go through sqlx's `Any` driver with `$1` placeholders, which both databases understand, so they are written
+// once, as the provided methods of `Storage`. What does differ, connecting and the schema's dialect, is all an
+// implementation picks. The config structs in config.rs load and update themselves through these methods.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use serenity::async_trait;
+use sqlx::any::{AnyConnectOptions, AnyKind, AnyPool, AnyPoolOptions};
+use sqlx::migrate::Migrator;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::sqlite::SqliteConnectOptions;
+use crate::audio::{Effect, Effects};
+use crate::backup::{self, Backup};
+use crate::filter::Policy;
+use crate::settings::{self as registry, Level};
+use crate::spam::Limit;
+
+/// Used when DATABASE_URL isn't set
+pub const DEFAULT_DATABASE_URL: &str = "sqlite:database.db";
+
+fn effects(rate: f32, pitch: f32, gain: f32, effect: String) -> Effects {
+    Effects { rate, pitch, gain, effect: effect.parse().unwrap_or(Effect::None) }
+}
+
+/// A database the bot can keep its settings in, and every query it runs on it
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Connection options for a DATABASE_URL with this database's scheme
+    fn options(url: &str) -> sqlx::Result<AnyConnectOptions> where Self: Sized;
+    /// Migrations creating and upgrading the schema, in this database's dialect
+    fn migrator() -> Migrator where Self: Sized;
+    fn new(pool: AnyPool) -> Self where Self: Sized;
+    fn pool(&self) -> &AnyPool;
+
+    // Servers
+
+    /// Add a server if it's new, in one statement so concurrent messages can't add it twice
+    async fn register_server(&self, server_id: i64) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO servers (id) VALUES ($1) ON CONFLICT(id) DO NOTHING")
+            .bind(server_id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    /// The voice and text channels of a registered server
+    async fn server_channels(&self, server_id: i64) -> sqlx::Result<(Option<i64>, Option<i64>)> {
+        sqlx::query_as("SELECT voice_channel, text_channel FROM servers WHERE id = $1")
+            .bind(server_id)
+            .fetch_one(self.pool())
+            .await
+    }
+
+    async fn update_voice_channel(&self, server_id: i64, channel_id: i64) -> sqlx::Result<()> {
+        sqlx::query("UPDATE servers SET voice_channel = $1 WHERE id = $2")
+            .bind(channel_id)
+            .bind(server_id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn update_text_channel(&self, server_id: i64, channel_id: i64) -> sqlx::Result<()> {
+        sqlx::query("UPDATE servers SET text_channel = $1 WHERE id = $2")
+            .bind(channel_id)
+            .bind(server_id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Users
+
+    /// A user's voice in a server, adding the user without one if they are new
+    async fn user_voice(&self, user_id: i64, server_id: i64) -> sqlx::Result<Option<String>> {
+        sqlx::query("INSERT INTO users (id, server_id) VALUES ($1, $2) ON CONFLICT(id, server_id) DO NOTHING")
+            .bind(user_id)
+            .bind(server_id)
+            .execute(self.pool())
+            .await?;
+        let (voice,): (Option<String>,) = sqlx::query_as("SELECT voice FROM users WHERE id = $1 AND server_id = $2")
+            .bind(user_id)
+            .bind(server_id)
+            .fetch_one(self.pool())
+            .await?;
+        Ok(voice)
+    }
+
+    /// Set a user's voice in a server, or forget it with `None`
+    async fn update_user_voice(&self, user_id: i64, server_id: i64, voice: Option<&str>) -> sqlx::Result<()> {
+        sqlx::query("UPDATE users SET voice = $1 WHERE id = $2 AND server_id = $3")
+            .bind(voice)
+            .bind(user_id)
+            .bind(server_id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn voice_options(&self, user_id: i64, server_id: i64) -> sqlx::Result<Option<Effects>> {
+        let options: Option<(f32, f32, f32, String)> = sqlx::query_as("SELECT rate, pitch, gain, effect FROM voice_options WHERE id = $1 AND server_id = $2")
+            .bind(user_id)
+            .bind(server_id)
+            .fetch_optional(self.pool())
+            .await?;
+        Ok(options.map(|(rate, pitch, gain, effect)| effects(rate, pitch, gain, effect)))
+    }
+
+    async fn update_voice_options(&self, user_id: i64, server_id: i64, effects: &Effects) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO voice_options (id, server_id, rate, pitch, gain, effect) VALUES ($1, $2, $3, $4, $5, $6) \
+            ON CONFLICT(id, server_id) DO UPDATE SET rate = excluded.rate, pitch = excluded.pitch, gain = excluded.gain, effect = excluded.effect")
+            .bind(user_id)
+            .bind(server_id)
+            .bind(effects.rate)
+            .bind(effects.pitch)
+            .bind(effects.gain)
+            .bind(effects.effect.to_string())
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_voice_options(&self, user_id: i64, server_id: i64) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM voice_options WHERE id = $1 AND server_id = $2")
+            .bind(user_id)
+            .bind(server_id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    /// The voices users set in a server, not those they inherit from their global profile
+    async fn user_voices(&self, server_id: i64) -> sqlx::Result<Vec<(i64, String)>> {
+        sqlx::query_as("SELECT id, voice FROM users WHERE server_id = $1 AND voice IS NOT NULL")
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await
+    }
+
+    /// The voice options users set in a server
+    async fn user_voice_options(&self, server_id: i64) -> sqlx::Result<Vec<(i64, Effects)>> {
+        let rows: Vec<(i64, f32, f32, f32, String)> = sqlx::query_as("SELECT id, rate, pitch, gain, effect FROM voice_options WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await?;
+        Ok(rows.into_iter().map(|(id, rate, pitch, gain, effect)| (id, effects(rate, pitch, gain, effect))).collect())
+    }
+
+    // Global profiles
+
+    /// A user's global voice, voice options and alias
+    async fn profile(&self, user_id: i64) -> sqlx::Result<(Option<String>, Option<Effects>, Option<String>)> {
+        let row: Option<(Option<String>, Option<f32>, Option<f32>, Option<f32>, Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT voice, rate, pitch, gain, effect, alias FROM user_profiles WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(self.pool())
+                .await?;
+        let (voice, rate, pitch, gain, effect, alias) = row.unwrap_or_default();
+        let options = match (rate, pitch, gain, effect) {
+            (Some(rate), Some(pitch), Some(gain), Some(effect)) => Some(effects(rate, pitch, gain, effect)),
+            _ => None,
+        };
+        Ok((voice, options, alias))
+    }
+
+    async fn save_profile(&self, user_id: i64, voice: Option<&str>, effects: Option<Effects>, alias: Option<&str>) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO user_profiles (id, voice, rate, pitch, gain, effect, alias) VALUES ($1, $2, $3, $4, $5, $6, $7) \
+            ON CONFLICT(id) DO UPDATE SET voice = excluded.voice, rate = excluded.rate, pitch = excluded.pitch, gain = excluded.gain, effect = excluded.effect, alias = excluded.alias")
+            .bind(user_id)
+            .bind(voice)
+            .bind(effects.map(|e| e.rate))
+            .bind(effects.map(|e| e.pitch))
+            .bind(effects.map(|e| e.gain))
+            .bind(effects.map(|e| e.effect.to_string()))
+            .bind(alias)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    /// Aliases of several users at once, for reading mentions
+    async fn aliases(&self, user_ids: &[i64]) -> sqlx::Result<HashMap<i64, String>> {
+        if user_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let placeholders = (1..=user_ids.len()).map(|i| format!("${}", i)).collect::<Vec<String>>().join(", ");
+        let sql = format!("SELECT id, alias FROM user_profiles WHERE alias IS NOT NULL AND id IN ({})", placeholders);
+        let mut query = sqlx::query_as::<_, (i64, String)>(&sql);
+        for id in user_ids {
+            query = query.bind(*id);
+        }
+        let rows = query.fetch_all(self.pool()).await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    // Dictionary
+
+    async fn dictionary(&self, server_id: i64) -> sqlx::Result<Vec<(String, String)>> {
+        sqlx::query_as("SELECT word, replacement FROM dictionary WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await
+    }
+
+    async fn add_dictionary_entry(&self, server_id: i64, word: &str, replacement: &str) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO dictionary (server_id, word, replacement) VALUES ($1, $2, $3) \
+            ON CONFLICT(server_id, word) DO UPDATE SET replacement = excluded.replacement")
+            .bind(server_id)
+            .bind(word)
+            .bind(replacement)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_dictionary_entry(&self, server_id: i64, word: &str) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM dictionary WHERE server_id = $1 AND word = $2")
+            .bind(server_id)
+            .bind(word)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Content filter
+
+    /// Whether the filter and the built-in word list are on, the policy and the replacement, if the server set them
+    async fn filter(&self, server_id: i64) -> sqlx::Result<Option<(bool, bool, String, String)>> {
+        sqlx::query_as("SELECT enabled, builtin, policy, replacement FROM filters WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_optional(self.pool())
+            .await
+    }
+
+    async fn save_filter(&self, server_id: i64, enabled: bool, builtin: bool, policy: Policy, replacement: &str) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO filters (server_id, enabled, builtin, policy, replacement) VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT(server_id) DO UPDATE SET enabled = excluded.enabled, builtin = excluded.builtin, policy = excluded.policy, replacement = excluded.replacement")
+            .bind(server_id)
+            .bind(enabled)
+            .bind(builtin)
+            .bind(policy.to_string())
+            .bind(replacement)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn filter_words(&self, server_id: i64) -> sqlx::Result<Vec<String>> {
+        let words: Vec<(String,)> = sqlx::query_as("SELECT word FROM filter_words WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await?;
+        Ok(words.into_iter().map(|(word,)| word).collect())
+    }
+
+    async fn add_filter_word(&self, server_id: i64, word: &str) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO filter_words (server_id, word) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(server_id)
+            .bind(word)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_filter_word(&self, server_id: i64, word: &str) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM filter_words WHERE server_id = $1 AND word = $2")
+            .bind(server_id)
+            .bind(word)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Rate limits
+
+    /// The user and server limits, if the server changed them
+    async fn spam_limits(&self, server_id: i64) -> sqlx::Result<Option<(Limit, Limit)>> {
+        let limits: Option<(i64, i64, i64, i64)> = sqlx::query_as("SELECT user_messages, user_seconds, server_messages, server_seconds FROM spam_settings WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_optional(self.pool())
+            .await?;
+        Ok(limits.map(|(user_messages, user_seconds, server_messages, server_seconds)| (
+            Limit { messages: user_messages as u32, seconds: user_seconds as u32 },
+            Limit { messages: server_messages as u32, seconds: server_seconds as u32 },
+        )))
+    }
+
+    async fn save_spam_limits(&self, server_id: i64, user_limit: Limit, server_limit: Limit) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO spam_settings (server_id, user_messages, user_seconds, server_messages, server_seconds) VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT(server_id) DO UPDATE SET user_messages = excluded.user_messages, user_seconds = excluded.user_seconds, server_messages = excluded.server_messages, server_seconds = excluded.server_seconds")
+            .bind(server_id)
+            // Postgres has no unsigned integers
+            .bind(user_limit.messages as i64)
+            .bind(user_limit.seconds as i64)
+            .bind(server_limit.messages as i64)
+            .bind(server_limit.seconds as i64)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Language voices
+
+    /// (user id, language, voice) of a user's and the server's (user id 0) mappings
+    async fn language_voices(&self, server_id: i64, user_id: i64) -> sqlx::Result<Vec<(i64, String, String)>> {
+        sqlx::query_as("SELECT user_id, language, voice FROM language_voices WHERE server_id = $1 AND user_id IN ($2, 0)")
+            .bind(server_id)
+            .bind(user_id)
+            .fetch_all(self.pool())
+            .await
+    }
+
+    /// Map a language to a voice, or remove the mapping with `None`
+    async fn set_language_voice(&self, server_id: i64, user_id: i64, language: &str, voice: Option<&str>) -> sqlx::Result<()> {
+        if let Some(voice) = voice {
+            sqlx::query("INSERT INTO language_voices (server_id, user_id, language, voice) VALUES ($1, $2, $3, $4) \
+                ON CONFLICT(server_id, user_id, language) DO UPDATE SET voice = excluded.voice")
+                .bind(server_id)
+                .bind(user_id)
+                .bind(language)
+                .bind(voice)
+                .execute(self.pool())
+                .await?;
+        } else {
+            sqlx::query("DELETE FROM language_voices WHERE server_id = $1 AND user_id = $2 AND language = $3")
+                .bind(server_id)
+                .bind(user_id)
+                .bind(language)
+                .execute(self.pool())
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Presets
+
+    /// (name, voice, effects) of every preset
+    async fn presets(&self, server_id: i64) -> sqlx::Result<Vec<(String, String, Effects)>> {
+        let rows: Vec<(String, String, f32, f32, f32, String)> = sqlx::query_as("SELECT name, voice, rate, pitch, gain, effect FROM presets WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await?;
+        Ok(rows.into_iter().map(|(name, voice, rate, pitch, gain, effect)| (name, voice, effects(rate, pitch, gain, effect))).collect())
+    }
+
+    async fn add_preset(&self, server_id: i64, name: &str, voice: &str, effects: &Effects) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO presets (server_id, name, voice, rate, pitch, gain, effect) VALUES ($1, $2, $3, $4, $5, $6, $7) \
+            ON CONFLICT(server_id, name) DO UPDATE SET voice = excluded.voice, rate = excluded.rate, pitch = excluded.pitch, gain = excluded.gain, effect = excluded.effect")
+            .bind(server_id)
+            .bind(name)
+            .bind(voice)
+            .bind(effects.rate)
+            .bind(effects.pitch)
+            .bind(effects.gain)
+            .bind(effects.effect.to_string())
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_preset(&self, server_id: i64, name: &str) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM presets WHERE server_id = $1 AND name = $2")
+            .bind(server_id)
+            .bind(name)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Voice restrictions
+
+    /// Whether the voice rules are an allowlist, if the server picked
+    async fn voice_allowlist(&self, server_id: i64) -> sqlx::Result<Option<bool>> {
+        let allowlist: Option<(bool,)> = sqlx::query_as("SELECT allowlist FROM voice_policy WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_optional(self.pool())
+            .await?;
+        Ok(allowlist.map(|(allowlist,)| allowlist))
+    }
+
+    async fn update_voice_allowlist(&self, server_id: i64, allowlist: bool) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO voice_policy (server_id, allowlist) VALUES ($1, $2) \
+            ON CONFLICT(server_id) DO UPDATE SET allowlist = excluded.allowlist")
+            .bind(server_id)
+            .bind(allowlist)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn voice_rules(&self, server_id: i64) -> sqlx::Result<Vec<String>> {
+        let entries: Vec<(String,)> = sqlx::query_as("SELECT entry FROM voice_rules WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await?;
+        Ok(entries.into_iter().map(|(entry,)| entry).collect())
+    }
+
+    async fn add_voice_rule(&self, server_id: i64, entry: &str) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO voice_rules (server_id, entry) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(server_id)
+            .bind(entry)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_voice_rule(&self, server_id: i64, entry: &str) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM voice_rules WHERE server_id = $1 AND entry = $2")
+            .bind(server_id)
+            .bind(entry)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Consent
+
+    /// (server id, opted in) of a user's choices in a server and globally (server 0)
+    async fn consent(&self, user_id: i64, server_id: i64) -> sqlx::Result<Vec<(i64, bool)>> {
+        sqlx::query_as("SELECT server_id, opted_in FROM consent WHERE user_id = $1 AND server_id IN (0, $2)")
+            .bind(user_id)
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await
+    }
+
+    /// Record whether a user opted in or out, or forget it with `None`
+    async fn set_consent(&self, server_id: i64, user_id: i64, opted_in: Option<bool>) -> sqlx::Result<()> {
+        if let Some(opted_in) = opted_in {
+            sqlx::query("INSERT INTO consent (server_id, user_id, opted_in) VALUES ($1, $2, $3) \
+                ON CONFLICT(server_id, user_id) DO UPDATE SET opted_in = excluded.opted_in")
+                .bind(server_id)
+                .bind(user_id)
+                .bind(opted_in)
+                .execute(self.pool())
+                .await?;
+        } else {
+            sqlx::query("DELETE FROM consent WHERE server_id = $1 AND user_id = $2")
+                .bind(server_id)
+                .bind(user_id)
+                .execute(self.pool())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn optin_only(&self, server_id: i64) -> sqlx::Result<Option<bool>> {
+        let optin_only: Option<(bool,)> = sqlx::query_as("SELECT optin_only FROM privacy_settings WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_optional(self.pool())
+            .await?;
+        Ok(optin_only.map(|(optin_only,)| optin_only))
+    }
+
+    async fn update_optin_only(&self, server_id: i64, optin_only: bool) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO privacy_settings (server_id, optin_only) VALUES ($1, $2) \
+            ON CONFLICT(server_id) DO UPDATE SET optin_only = excluded.optin_only")
+            .bind(server_id)
+            .bind(optin_only)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Blocks and roles
+
+    /// (kind, id) of every blocked user and role
+    async fn blocks(&self, server_id: i64) -> sqlx::Result<Vec<(String, i64)>> {
+        sqlx::query_as("SELECT kind, target_id FROM blocks WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await
+    }
+
+    async fn add_block(&self, server_id: i64, kind: &str, target_id: i64) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO blocks (server_id, kind, target_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING")
+            .bind(server_id)
+            .bind(kind)
+            .bind(target_id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_block(&self, server_id: i64, kind: &str, target_id: i64) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM blocks WHERE server_id = $1 AND kind = $2 AND target_id = $3")
+            .bind(server_id)
+            .bind(kind)
+            .bind(target_id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn tts_role(&self, server_id: i64) -> sqlx::Result<Option<i64>> {
+        let tts_role: Option<(Option<i64>,)> = sqlx::query_as("SELECT tts_role FROM access_settings WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_optional(self.pool())
+            .await?;
+        Ok(tts_role.and_then(|(role,)| role))
+    }
+
+    async fn update_tts_role(&self, server_id: i64, role: Option<i64>) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO access_settings (server_id, tts_role) VALUES ($1, $2) \
+            ON CONFLICT(server_id) DO UPDATE SET tts_role = excluded.tts_role")
+            .bind(server_id)
+            .bind(role)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn dj_role(&self, server_id: i64) -> sqlx::Result<Option<i64>> {
+        let dj_role: Option<(Option<i64>,)> = sqlx::query_as("SELECT dj_role FROM permission_settings WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_optional(self.pool())
+            .await?;
+        Ok(dj_role.and_then(|(role,)| role))
+    }
+
+    async fn update_dj_role(&self, server_id: i64, role: Option<i64>) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO permission_settings (server_id, dj_role) VALUES ($1, $2) \
+            ON CONFLICT(server_id) DO UPDATE SET dj_role = excluded.dj_role")
+            .bind(server_id)
+            .bind(role)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Ignored prefixes
+
+    async fn ignored_prefixes(&self, server_id: i64) -> sqlx::Result<Vec<String>> {
+        let ignored: Vec<(String,)> = sqlx::query_as("SELECT entry FROM ignored_prefixes WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_all(self.pool())
+            .await?;
+        Ok(ignored.into_iter().map(|(entry,)| entry).collect())
+    }
+
+    async fn add_ignored_prefix(&self, server_id: i64, entry: &str) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO ignored_prefixes (server_id, entry) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(server_id)
+            .bind(entry)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_ignored_prefix(&self, server_id: i64, entry: &str) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM ignored_prefixes WHERE server_id = $1 AND entry = $2")
+            .bind(server_id)
+            .bind(entry)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Settings registry
+
+    /// (level, key, value) of the settings stored for the user, channel and server targets, and globally
+    async fn settings(&self, targets: [(Level, i64, i64); 3]) -> sqlx::Result<Vec<(String, String, String)>> {
+        let mut query = sqlx::query_as::<_, (String, String, String)>("SELECT level, key, value FROM settings WHERE server_id = $1 AND target_id = $2 AND level = $3 \
+            OR server_id = $4 AND target_id = $5 AND level = $6 \
+            OR server_id = $7 AND target_id = $8 AND level = $9 \
+            OR server_id = 0 AND target_id = 0 AND level = 'global'");
+        for (level, server_id, target_id) in targets {
+            query = query.bind(server_id).bind(target_id).bind(level.to_string());
+        }
+        query.fetch_all(self.pool()).await
+    }
+
+    async fn set_setting(&self, level: Level, server_id: i64, target_id: i64, key: &str, value: &str) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO settings (level, server_id, target_id, key, value) VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT(level, server_id, target_id, key) DO UPDATE SET value = excluded.value")
+            .bind(level.to_string())
+            .bind(server_id)
+            .bind(target_id)
+            .bind(key)
+            .bind(value)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_setting(&self, level: Level, server_id: i64, target_id: i64, key: &str) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM settings WHERE level = $1 AND server_id = $2 AND target_id = $3 AND key = $4")
+            .bind(level.to_string())
+            .bind(server_id)
+            .bind(target_id)
+            .bind(key)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Backups
+
+    /// Replace a server's dictionary, presets, filter and settings with a file's, and set the voices of the users in it.
+    /// Users who aren't in the file keep theirs. Everything is written at once, or nothing is if a query fails.
+    async fn restore_backup(&self, server_id: i64, file: &Backup) -> sqlx::Result<()> {
+        let mut transaction = self.pool().begin().await?;
+        sqlx::query("INSERT INTO servers (id) VALUES ($1) ON CONFLICT(id) DO NOTHING")
+            .bind(server_id)
+            .execute(&mut transaction)
+            .await?;
+        if let Some(channel_id) = file.voice_channel.as_deref().and_then(backup::parse_id) {
+            sqlx::query("UPDATE servers SET voice_channel = $1 WHERE id = $2")
+                .bind(channel_id)
+                .bind(server_id)
+                .execute(&mut transaction)
+                .await?;
+        }
+        if let Some(channel_id) = file.text_channel.as_deref().and_then(backup::parse_id) {
+            sqlx::query("UPDATE servers SET text_channel = $1 WHERE id = $2")
+                .bind(channel_id)
+                .bind(server_id)
+                .execute(&mut transaction)
+                .await?;
+        }
+
+        for table in ["dictionary", "presets", "filter_words"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE server_id = $1", table))
+                .bind(server_id)
+                .execute(&mut transaction)
+                .await?;
+        }
+        for (word, replacement) in &file.dictionary {
+            sqlx::query("INSERT INTO dictionary (server_id, word, replacement) VALUES ($1, $2, $3) \
+                ON CONFLICT(server_id, word) DO UPDATE SET replacement = excluded.replacement")
+                .bind(server_id)
+                .bind(word.to_lowercase())
+                .bind(replacement)
+                .execute(&mut transaction)
+                .await?;
+        }
+        for preset in &file.presets {
+            let effects = preset.options.effects();
+            sqlx::query("INSERT INTO presets (server_id, name, voice, rate, pitch, gain, effect) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+                .bind(server_id)
+                .bind(preset.name.to_lowercase())
+                .bind(&preset.voice)
+                .bind(effects.rate)
+                .bind(effects.pitch)
+                .bind(effects.gain)
+                .bind(effects.effect.to_string())
+                .execute(&mut transaction)
+                .await?;
+        }
+        sqlx::query("INSERT INTO filters (server_id, enabled, builtin, policy, replacement) VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT(server_id) DO UPDATE SET enabled = excluded.enabled, builtin = excluded.builtin, policy = excluded.policy, replacement = excluded.replacement")
+            .bind(server_id)
+            .bind(file.filter.enabled)
+            .bind(file.filter.builtin)
+            .bind(file.filter.policy.parse().unwrap_or(Policy::Bleep).to_string())
+            .bind(&file.filter.replacement)
+            .execute(&mut transaction)
+            .await?;
+        for word in &file.filter.words {
+            sqlx::query("INSERT INTO filter_words (server_id, word) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+                .bind(server_id)
+                .bind(word.to_lowercase())
+                .execute(&mut transaction)
+                .await?;
+        }
+        if let Some(settings) = &file.settings {
+            sqlx::query("DELETE FROM settings WHERE level = $1 AND server_id = $2 AND target_id = $3")
+                .bind(Level::Server.to_string())
+                .bind(server_id)
+                .bind(server_id)
+                .execute(&mut transaction)
+                .await?;
+            for (name, value) in settings {
+                // Checked by `backup::parse`, this only stores the value the way `set` would
+                let (name, value) = match registry::find(name).and_then(|setting| Some((setting.name(), setting.normalize(value).ok()?))) {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                sqlx::query("INSERT INTO settings (level, server_id, target_id, key, value) VALUES ($1, $2, $3, $4, $5)")
+                    .bind(Level::Server.to_string())
+                    .bind(server_id)
+                    .bind(server_id)
+                    .bind(name)
+                    .bind(value)
+                    .execute(&mut transaction)
+                    .await?;
+            }
+        }
+
+        for user in &file.users {
+            let user_id = match backup::parse_id(&user.id) {
+                Some(user_id) => user_id,
+                None => continue,
+            };
+            sqlx::query("INSERT INTO users (id, server_id, voice) VALUES ($1, $2, $3) \
+                ON CONFLICT(id, server_id) DO UPDATE SET voice = excluded.voice")
+                .bind(user_id)
+                .bind(server_id)
+                .bind(&user.voice)
+                .execute(&mut transaction)
+                .await?;
+            match &user.options {
+                Some(options) => {
+                    let effects = options.effects();
+                    sqlx::query("INSERT INTO voice_options (id, server_id, rate, pitch, gain, effect) VALUES ($1, $2, $3, $4, $5, $6) \
+                        ON CONFLICT(id, server_id) DO UPDATE SET rate = excluded.rate, pitch = excluded.pitch, gain = excluded.gain, effect = excluded.effect")
+                        .bind(user_id)
+                        .bind(server_id)
+                        .bind(effects.rate)
+                        .bind(effects.pitch)
+                        .bind(effects.gain)
+                        .bind(effects.effect.to_string())
+                        .execute(&mut transaction)
+                        .await?;
+                }
+                None => {
+                    sqlx::query("DELETE FROM voice_options WHERE id = $1 AND server_id = $2")
+                        .bind(user_id)
+                        .bind(server_id)
+                        .execute(&mut transaction)
+                        .await?;
+                }
+            }
+        }
+        transaction.commit().await    }
+}
+
+pub struct Sqlite(AnyPool);
+
+impl Storage for Sqlite {
+    fn options(url: &str) -> sqlx::Result<AnyConnectOptions> {
+        Ok(SqliteConnectOptions::from_str(url)?.create_if_missing(true).into())
+    }
+
+    fn migrator() -> Migrator {
+        sqlx::migrate!("migrations/sqlite")
+    }
+
+    fn new(pool: AnyPool) -> Self {
+        Sqlite(pool)
+    }
+
+    fn pool(&self) -> &AnyPool {
+        &self.0
+    }
+}
+
+pub struct Postgres(AnyPool);
+
+impl Storage for Postgres {
+    fn options(url: &str) -> sqlx::Result<AnyConnectOptions> {
+        Ok(PgConnectOptions::from_str(url)?.into())
+    }
+
+    fn migrator() -> Migrator {
+        sqlx::migrate!("migrations/postgres")
+    }
+
+    fn new(pool: AnyPool) -> Self {
+        Postgres(pool)
+    }
+
+    fn pool(&self) -> &AnyPool {
+        &self.0
+    }
+}
+
+/// Connect to the database at `url`, `sqlite:` or `postgres://`, and bring its schema up to date
+pub async fn connect(url: &str) -> anyhow::Result<Arc<dyn Storage>> {
+    Ok(match AnyKind::from_str(url)? {
+        AnyKind::Sqlite => Arc::new(open::<Sqlite>(url).await?),
+        AnyKind::Postgres => Arc::new(open::<Postgres>(url).await?),
+    })
+}
+
+async fn open<S: Storage>(url: &str) -> anyhow::Result<S> {
+    let pool = AnyPoolOptions::new().connect_with(S::options(url)?).await?;
+    // Migrations that already ran are skipped
+    S::migrator().run(&pool).await?;
+    Ok(S::new(pool))
+}
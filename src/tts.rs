@@ -1,12 +1,15 @@
 use std::{collections::HashMap};
 use bytes::{Bytes};
-use anyhow::{ Result};
+use anyhow::{anyhow, bail, Result};
 use reqwest::{Client, Response};
 use serde_json::{Value, json};
 use serde::{Serialize, Deserialize};
-use serenity::model::channel::Message;
 use tokio::{fs::File, io::AsyncWriteExt};
 use std::io::Cursor;
+use crate::config::Scope;
+use crate::markup::Tag;
+use crate::pipeline::Rate;
+use crate::{audio, voices};
 static TIKTOK_API_URL: &str = "https://api16-normal-useast5.us.tiktokv.com/media/api/text/speech/invoke/";
 static TTSMP3_API_URL: &str = "https://ttsmp3.com/makemp3_new.php";
 /* msg: your text here
@@ -43,22 +46,103 @@ pub enum TTS {
 }
 
 
+/// Name of the file a message's audio is written to, relative to the `tts` folder.
+/// Name format: server_id/channel_id/user_id.mp3
+pub fn file_name(scope: Option<Scope>) -> String {
+    if let Some(scope) = scope {
+        let mut path = std::env::current_dir().unwrap();
+        path.push("tts");
+        let channelid = scope.channel_id.0;
+        let serverid = scope.guild_id.unwrap().0;
+        path.push(&serverid.to_string());
+        path.push(&channelid.to_string());
+        std::fs::create_dir_all(path).unwrap();
+        format!("{}/{}/{}.mp3", scope.guild_id.unwrap().0, scope.channel_id.0, scope.user_id.0)
+    } else {
+        "test.mp3".to_string()
+    }
+}
+
+/// Whether a voice's backend understands SSML-style markup.
+/// ttsmp3 passes it on to Amazon Polly, and SAPI has its own XML tags.
+pub fn supports_ssml(voice: &str) -> bool {
+    voice.starts_with("ttsmp3-") || voice.starts_with("sapi-")
+}
+
+/// Markup for a pause, for backends that support SSML
+pub fn ssml_pause(voice: &str, ms: u32) -> String {
+    if voice.starts_with("sapi-") {
+        format!("<silence msec=\"{}\"/>", ms)
+    } else {
+        format!("<break time=\"{}ms\"/>", ms)
+    }
+}
+
+/// Markup for reading text at a different speed, for backends that support SSML
+pub fn ssml_rate(voice: &str, rate: Rate, text: &str) -> String {
+    let (sapi, polly) = match rate {
+        Rate::Normal => return text.to_string(),
+        Rate::Slow => ("-4", "slow"),
+        Rate::Fast => ("4", "fast"),
+    };
+    if voice.starts_with("sapi-") {
+        format!("<rate speed=\"{}\">{}</rate>", sapi, text)
+    } else {
+        format!("<prosody rate=\"{}\">{}</prosody>", polly, text)
+    }
+}
+
+/// Markup for a user's rate multiplier and pitch shift in semitones, for backends that support SSML
+pub fn ssml_prosody(voice: &str, rate: f32, pitch: f32, text: &str) -> String {
+    if rate == 1.0 && pitch == 0.0 {
+        return text.to_string();
+    }
+    if voice.starts_with("sapi-") {
+        // SAPI rates go from -10 (a third of the speed) to 10 (three times the speed)
+        let speed = (10.0 * rate.ln() / 3f32.ln()).round().clamp(-10.0, 10.0);
+        let middle = pitch.round().clamp(-10.0, 10.0);
+        format!("<rate speed=\"{}\"><pitch middle=\"{}\">{}</pitch></rate>", speed, middle, text)
+    } else {
+        let percent = ((2f32.powf(pitch / 12.0) - 1.0) * 100.0).round();
+        format!("<prosody rate=\"{}%\" pitch=\"{:+}%\">{}</prosody>", (rate * 100.0).round(), percent, text)
+    }
+}
+
+/// Escape text for a backend that reads SSML, so it's read as text
+pub fn escape_ssml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Whether an SSML tag from a message is in the dialect a voice's backend understands
+pub fn accepts_tag(voice: &str, tag: &Tag) -> bool {
+    supports_ssml(voice) && voice.starts_with("sapi-") == tag.sapi
+}
+
+/// The language a voice speaks, as an ISO 639-1 code.
+/// Used to pick the text normalization rules for a message.
+pub fn voice_language(voice: &str) -> &'static str {
+    if let Some(voice) = voices::find(voice) {
+        return voice.language;
+    }
+    // Not in the catalog, guess from TikTok's naming scheme
+    let (provider, id) = voice.split_once('-').unwrap_or(("", voice));
+    match (provider, id.split('_').next().unwrap_or_default()) {
+        ("tiktok", "fr") => "fr",
+        ("tiktok", "de") => "de",
+        ("tiktok", "es") => "es",
+        ("tiktok", "br") => "pt",
+        ("tiktok", "id") => "id",
+        ("tiktok", "jp") => "ja",
+        ("tiktok", "kr") => "ko",
+        _ => "en",
+    }
+}
+
+
 impl TTS {
-    pub async fn request(lang: &str, text: &str, msg: Option<&Message>) -> Result<Self, String> {
+    pub async fn request(lang: &str, text: &str, scope: Option<Scope>) -> Result<Self, String> {
         let client = Client::new();
-        // Name format: server_id-channel_id-user_id
-        let name = if let Some(msg) = msg {
-            let mut path = std::env::current_dir().unwrap();
-            path.push("tts");
-            let channelid = msg.channel_id.0;
-            let serverid = msg.guild_id.unwrap().0;
-            path.push(&serverid.to_string());
-            path.push(&channelid.to_string());
-            std::fs::create_dir_all(path).unwrap();
-            format!("{}/{}/{}.mp3", msg.guild_id.unwrap().0, msg.channel_id.0, msg.author.id.0)
-        } else {
-            "test.mp3".to_string()
-        };
+        let name = file_name(scope);
         if lang.starts_with("tiktok-") {
             let lang = lang.split("-").nth(1).unwrap();
             let param = [("text_speaker", lang), ("req_text", text)];
@@ -140,46 +224,38 @@ impl TTS {
 
     pub async fn download(self) -> Result<String> {
         // Match the type
-        match self {
-            TTS::TikTok { name, data, .. } => {
+        let (name, data) = match self {
+            TTS::TikTok { name, data, status_code, status_msg, .. } => {
+                if status_code != 0 {
+                    bail!("TikTok returned an error: {}", status_msg);
+                }
                 // So the data is a base64 encoded string, which we need to decode to an mp3 file
 
-                let data_json = data.unwrap();
+                let data_json = data.ok_or_else(|| anyhow!("TikTok returned no data"))?;
                 // First, we need to get the base64 encoded string
-                let data_base64 = data_json["v_str"].as_str().unwrap();
+                let data_base64 = data_json["v_str"].as_str().ok_or_else(|| anyhow!("TikTok returned no audio"))?;
 
                 // Then, we need to decode the base64 string
-                let data_raw = base64::decode(data_base64).unwrap();
-
-                let name = format!("tts/{}", name);
-                // Write the data to a file
-                let mut file = File::create(name.clone()).await.unwrap();
-                file.write_all(&data_raw).await.unwrap();
-                Ok(name)
+                (name, Bytes::from(base64::decode(data_base64)?))
             }
 
             TTS::TTSMP3 { name, url, .. } => {
                 // Download the file from the URL
-                let name = format!("tts/{}", name);
-                let response = reqwest::get(url).await.unwrap();
-                let mut file = tokio::fs::File::create(name.clone()).await.expect("Failed to create file");
-                let mut content =  Cursor::new(response.bytes().await.unwrap());
-                tokio::io::copy(&mut content, &mut file).await.expect("Failed to copy file");
-                Ok(name)
+                let response = reqwest::get(url).await?;
+                (name, response.bytes().await?)
             }
 
-            TTS::OmameSAPI { name, data, .. } => {
-                // Write the data to a file
-                //let name = name.replace(".mp3", ".wav");
-                let name = format!("tts/{}", name);
-                let mut file = tokio::fs::File::create(name.clone()).await.expect("Failed to create file");
-                let mut content =  Cursor::new(data);
-                tokio::io::copy(&mut content, &mut file).await.expect("Failed to copy file");
-                Ok(name)
-            }
-
-        }
+            // SAPI returns WAV rather than MP3, which the format check below takes care of
+            TTS::OmameSAPI { name, data, .. } => (name, data),
+        };
 
+        // Make sure we actually got audio, and name the file after what it really is
+        let format = audio::sniff(&data)?;
+        let name = format!("tts/{}.{}", name.trim_end_matches(".mp3"), format.extension());
+        // Write the data to a file
+        let mut file = File::create(name.clone()).await?;
+        file.write_all(&data).await?;
+        Ok(name)
     }
 }
 
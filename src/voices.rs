@@ -0,0 +1,230 @@
+// Catalog of every voice the bot knows about, per backend.
+// Voice ids are `<provider>-<voice>`, the same format `TTS::request` takes.
+
+use std::fmt;
+
+/// Voice new users get, unless the server picked another one, see `settings::VOICE`
+pub const DEFAULT_VOICE: &str = "tiktok-en_us_002";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gender {
+    Female,
+    Male,
+    Neutral,
+}
+
+impl fmt::Display for Gender {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Gender::Female => write!(f, "female"),
+            Gender::Male => write!(f, "male"),
+            Gender::Neutral => write!(f, "neutral"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Voice {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// ISO 639-1 code
+    pub language: &'static str,
+    pub gender: Gender,
+}
+
+impl Voice {
+    pub fn provider(&self) -> &'static str {
+        self.id.split('-').next().unwrap_or_default()
+    }
+}
+
+const fn voice(id: &'static str, name: &'static str, language: &'static str, gender: Gender) -> Voice {
+    Voice { id, name, language, gender }
+}
+
+use Gender::*;
+
+pub static PROVIDERS: &[&str] = &["tiktok", "ttsmp3", "sapi"];
+
+pub static CATALOG: &[Voice] = &[
+    // TikTok
+    voice("tiktok-en_us_001", "US Female 1", "en", Female),
+    voice("tiktok-en_us_002", "US Female 2", "en", Female),
+    voice("tiktok-en_us_006", "US Male 1", "en", Male),
+    voice("tiktok-en_us_007", "US Male 2", "en", Male),
+    voice("tiktok-en_us_009", "US Male 3", "en", Male),
+    voice("tiktok-en_us_010", "US Male 4", "en", Male),
+    voice("tiktok-en_uk_001", "UK Male 1", "en", Male),
+    voice("tiktok-en_uk_003", "UK Male 2", "en", Male),
+    voice("tiktok-en_au_001", "Australian Female", "en", Female),
+    voice("tiktok-en_au_002", "Australian Male", "en", Male),
+    voice("tiktok-en_male_narration", "Narrator", "en", Male),
+    voice("tiktok-en_male_funny", "Wacky", "en", Male),
+    voice("tiktok-en_female_emotional", "Peaceful", "en", Female),
+    voice("tiktok-en_us_ghostface", "Ghost Face", "en", Male),
+    voice("tiktok-en_us_chewbacca", "Chewbacca", "en", Neutral),
+    voice("tiktok-en_us_c3po", "C3PO", "en", Male),
+    voice("tiktok-en_us_stitch", "Stitch", "en", Neutral),
+    voice("tiktok-en_us_stormtrooper", "Stormtrooper", "en", Male),
+    voice("tiktok-en_us_rocket", "Rocket", "en", Male),
+    voice("tiktok-en_female_f08_salut_damour", "Alto (singing)", "en", Female),
+    voice("tiktok-en_male_m03_lobby", "Tenor (singing)", "en", Male),
+    voice("tiktok-en_male_m03_sunshine_soon", "Sunshine Soon (singing)", "en", Male),
+    voice("tiktok-en_female_f08_warmy_breeze", "Warmy Breeze (singing)", "en", Female),
+    voice("tiktok-en_female_ht_f08_glorious", "Glorious (singing)", "en", Female),
+    voice("tiktok-en_male_sing_funny_it_goes_up", "It Goes Up (singing)", "en", Male),
+    voice("tiktok-en_male_m2_xhxs_m03_silly", "Chipmunk (singing)", "en", Male),
+    voice("tiktok-en_female_ht_f08_wonderful_world", "Dramatic (singing)", "en", Female),
+    voice("tiktok-fr_001", "French Male 1", "fr", Male),
+    voice("tiktok-fr_002", "French Male 2", "fr", Male),
+    voice("tiktok-de_001", "German Female", "de", Female),
+    voice("tiktok-de_002", "German Male", "de", Male),
+    voice("tiktok-es_002", "Spanish Male", "es", Male),
+    voice("tiktok-es_mx_002", "Spanish (Mexico) Male", "es", Male),
+    voice("tiktok-br_001", "Portuguese (Brazil) Female 1", "pt", Female),
+    voice("tiktok-br_003", "Portuguese (Brazil) Female 2", "pt", Female),
+    voice("tiktok-br_004", "Portuguese (Brazil) Female 3", "pt", Female),
+    voice("tiktok-br_005", "Portuguese (Brazil) Male", "pt", Male),
+    voice("tiktok-id_001", "Indonesian Female", "id", Female),
+    voice("tiktok-jp_001", "Japanese Female 1", "ja", Female),
+    voice("tiktok-jp_003", "Japanese Female 2", "ja", Female),
+    voice("tiktok-jp_005", "Japanese Female 3", "ja", Female),
+    voice("tiktok-jp_006", "Japanese Male", "ja", Male),
+    voice("tiktok-kr_002", "Korean Male 1", "ko", Male),
+    voice("tiktok-kr_003", "Korean Female", "ko", Female),
+    voice("tiktok-kr_004", "Korean Male 2", "ko", Male),
+    // ttsmp3, which uses Amazon Polly voices
+    voice("ttsmp3-Ivy", "Ivy (US)", "en", Female),
+    voice("ttsmp3-Joanna", "Joanna (US)", "en", Female),
+    voice("ttsmp3-Kendra", "Kendra (US)", "en", Female),
+    voice("ttsmp3-Kimberly", "Kimberly (US)", "en", Female),
+    voice("ttsmp3-Salli", "Salli (US)", "en", Female),
+    voice("ttsmp3-Joey", "Joey (US)", "en", Male),
+    voice("ttsmp3-Justin", "Justin (US)", "en", Male),
+    voice("ttsmp3-Matthew", "Matthew (US)", "en", Male),
+    voice("ttsmp3-Amy", "Amy (UK)", "en", Female),
+    voice("ttsmp3-Emma", "Emma (UK)", "en", Female),
+    voice("ttsmp3-Brian", "Brian (UK)", "en", Male),
+    voice("ttsmp3-Nicole", "Nicole (Australia)", "en", Female),
+    voice("ttsmp3-Russell", "Russell (Australia)", "en", Male),
+    voice("ttsmp3-Raveena", "Raveena (India)", "en", Female),
+    voice("ttsmp3-Geraint", "Geraint (Wales)", "en", Male),
+    voice("ttsmp3-Mizuki", "Mizuki", "ja", Female),
+    voice("ttsmp3-Takumi", "Takumi", "ja", Male),
+    voice("ttsmp3-Seoyeon", "Seoyeon", "ko", Female),
+    voice("ttsmp3-Zhiyu", "Zhiyu", "zh", Female),
+    voice("ttsmp3-Celine", "Céline (France)", "fr", Female),
+    voice("ttsmp3-Lea", "Léa (France)", "fr", Female),
+    voice("ttsmp3-Mathieu", "Mathieu (France)", "fr", Male),
+    voice("ttsmp3-Chantal", "Chantal (Canada)", "fr", Female),
+    voice("ttsmp3-Marlene", "Marlene", "de", Female),
+    voice("ttsmp3-Vicki", "Vicki", "de", Female),
+    voice("ttsmp3-Hans", "Hans", "de", Male),
+    voice("ttsmp3-Conchita", "Conchita (Spain)", "es", Female),
+    voice("ttsmp3-Lucia", "Lucia (Spain)", "es", Female),
+    voice("ttsmp3-Enrique", "Enrique (Spain)", "es", Male),
+    voice("ttsmp3-Mia", "Mia (Mexico)", "es", Female),
+    voice("ttsmp3-Lupe", "Lupe (US)", "es", Female),
+    voice("ttsmp3-Penelope", "Penélope (US)", "es", Female),
+    voice("ttsmp3-Miguel", "Miguel (US)", "es", Male),
+    voice("ttsmp3-Camila", "Camila (Brazil)", "pt", Female),
+    voice("ttsmp3-Vitoria", "Vitória (Brazil)", "pt", Female),
+    voice("ttsmp3-Ricardo", "Ricardo (Brazil)", "pt", Male),
+    voice("ttsmp3-Ines", "Inês (Portugal)", "pt", Female),
+    voice("ttsmp3-Cristiano", "Cristiano (Portugal)", "pt", Male),
+    voice("ttsmp3-Carla", "Carla", "it", Female),
+    voice("ttsmp3-Bianca", "Bianca", "it", Female),
+    voice("ttsmp3-Giorgio", "Giorgio", "it", Male),
+    voice("ttsmp3-Lotte", "Lotte", "nl", Female),
+    voice("ttsmp3-Ruben", "Ruben", "nl", Male),
+    voice("ttsmp3-Tatyana", "Tatyana", "ru", Female),
+    voice("ttsmp3-Maxim", "Maxim", "ru", Male),
+    voice("ttsmp3-Ewa", "Ewa", "pl", Female),
+    voice("ttsmp3-Jacek", "Jacek", "pl", Male),
+    voice("ttsmp3-Filiz", "Filiz", "tr", Female),
+    voice("ttsmp3-Zeina", "Zeina", "ar", Female),
+    // Omame's SAPI server
+    voice("sapi-Microsoft Sam", "Microsoft Sam", "en", Male),
+    voice("sapi-Microsoft Mike", "Microsoft Mike", "en", Male),
+    voice("sapi-Microsoft Mary", "Microsoft Mary", "en", Female),
+];
+
+/// Look up a voice by id, ignoring case. Use the returned voice's `id` when storing it,
+/// since the backends are case sensitive.
+pub fn find(id: &str) -> Option<&'static Voice> {
+    CATALOG.iter().find(|voice| voice.id.eq_ignore_ascii_case(id))
+}
+
+/// Every voice matching a provider and/or language
+pub fn search(provider: Option<&str>, language: Option<&str>) -> Vec<&'static Voice> {
+    CATALOG
+        .iter()
+        .filter(|voice| provider.is_none_or(|p| voice.provider().eq_ignore_ascii_case(p)))
+        .filter(|voice| language.is_none_or(|l| voice.language.eq_ignore_ascii_case(l)))
+        .collect()
+}
+
+/// Find a voice at the start of some text, returning it and the rest of the text.
+/// SAPI voice names have spaces in them, so the longest match wins.
+pub fn find_prefix(text: &str) -> Option<(&'static Voice, &str)> {
+    let text = text.trim();
+    let ends = text.match_indices(char::is_whitespace).map(|(i, _)| i).chain([text.len()]);
+    ends.collect::<Vec<usize>>()
+        .into_iter()
+        .rev()
+        .find_map(|end| find(&text[..end]).map(|voice| (voice, text[end..].trim())))
+}
+
+/// Voices matching part of an id or name, for autocompletion. Ids starting with the query come first.
+pub fn suggest(query: &str) -> Vec<&'static Voice> {
+    let query = query.trim().to_lowercase();
+    let (mut prefixed, mut contained): (Vec<&Voice>, Vec<&Voice>) = (Vec::new(), Vec::new());
+    for voice in CATALOG {
+        let id = voice.id.to_lowercase();
+        if id.starts_with(&query) {
+            prefixed.push(voice);
+        } else if id.contains(&query) || voice.name.to_lowercase().contains(&query) {
+            contained.push(voice);
+        }
+    }
+    prefixed.append(&mut contained);
+    prefixed
+}
+
+/// A sentence to preview a voice with
+pub fn sample_text(language: &str) -> &'static str {
+    match language {
+        "ja" => "こんにちは！これが私の声です。",
+        "ko" => "안녕하세요! 제 목소리는 이렇습니다.",
+        "zh" => "你好！这就是我的声音。",
+        "fr" => "Bonjour ! Voici à quoi ressemble ma voix.",
+        "de" => "Hallo! So klingt meine Stimme.",
+        "es" => "¡Hola! Así es como suena mi voz.",
+        "pt" => "Olá! É assim que a minha voz soa.",
+        "it" => "Ciao! Ecco come suona la mia voce.",
+        "id" => "Halo! Beginilah suara saya.",
+        "nl" => "Hallo! Zo klinkt mijn stem.",
+        "ru" => "Привет! Вот как звучит мой голос.",
+        "pl" => "Cześć! Tak brzmi mój głos.",
+        "tr" => "Merhaba! Sesim böyle duyuluyor.",
+        "ar" => "مرحبا! هكذا يبدو صوتي.",
+        _ => "Hello! This is what my voice sounds like.",
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(suggest("").len(), CATALOG.len());
+        let ids = suggest("brian").iter().map(|voice| voice.id).collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["ttsmp3-Brian"]);
+        // Prefix matches come before matches further in
+        let ids = suggest("tiktok-jp").iter().map(|voice| voice.id).collect::<Vec<&str>>();
+        assert_eq!(ids[0], "tiktok-jp_001");
+        assert!(suggest("japanese").iter().all(|voice| voice.language == "ja"));
+        assert!(suggest("nonexistent").is_empty());
+    }
+}